@@ -0,0 +1,114 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use epic_manifest_wasm::manifest::{
+    self,
+    builder::{ChunkingStrategy, ManifestBuilder},
+    FManifest,
+};
+
+/// Counts allocated bytes so the memory cost of a parse can be reported next to its timing
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Small chunks keep the generator fast while still producing large chunk lists
+const SYNTHETIC_CHUNK_SIZE: u32 = 1024;
+
+/// (file count, chunks per file)
+const SIZES: [(usize, usize); 3] = [(10, 10), (100, 20), (1000, 20)];
+
+/// Builds a manifest with `file_count` files of `chunks_per_file` unique chunks each
+fn synthetic_manifest(file_count: usize, chunks_per_file: usize) -> FManifest {
+    let mut builder = ManifestBuilder::new("SyntheticApp", "1.0.0").launch("Game.exe", "");
+
+    for file_index in 0..file_count {
+        // Every chunk gets its own content so none of them are deduplicated
+        let data = (0..chunks_per_file)
+            .flat_map(|chunk_index| {
+                let seed = ((file_index * chunks_per_file + chunk_index) as u32).to_le_bytes();
+                seed.repeat(SYNTHETIC_CHUNK_SIZE as usize / seed.len())
+            })
+            .collect::<Vec<u8>>();
+
+        builder
+            .add_file(
+                &format!("Content/Paks/file_{}.pak", file_index),
+                &data,
+                ChunkingStrategy::FixedSize(SYNTHETIC_CHUNK_SIZE),
+            )
+            .expect("failed to add a synthetic file");
+    }
+
+    builder.build().0
+}
+
+fn label(file_count: usize, chunks_per_file: usize) -> String {
+    format!("{}x{}", file_count, chunks_per_file)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for (file_count, chunks_per_file) in SIZES {
+        let bytes = synthetic_manifest(file_count, chunks_per_file)
+            .serialize()
+            .expect("failed to serialize the synthetic manifest");
+
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        manifest::parse(&bytes).expect("failed to parse the synthetic manifest");
+        eprintln!(
+            "parse {}: {} bytes of manifest, {} bytes allocated",
+            label(file_count, chunks_per_file),
+            bytes.len(),
+            ALLOCATED.load(Ordering::Relaxed) - before
+        );
+
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(label(file_count, chunks_per_file)),
+            &bytes,
+            |b, bytes| b.iter(|| manifest::parse(bytes).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+
+    for (file_count, chunks_per_file) in SIZES {
+        let manifest = synthetic_manifest(file_count, chunks_per_file);
+        let size = manifest.serialize().unwrap().len();
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(label(file_count, chunks_per_file)),
+            &manifest,
+            |b, manifest| b.iter(|| manifest.serialize().unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_serialize);
+criterion_main!(benches);
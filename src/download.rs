@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::manifest::{
+    chunks::FChunk, file_manifest::FFileManifest, shared::FGuid, FManifestParser,
+};
+
+/// Downloads and assembles a single file of the manifest.
+///
+/// `fetch` is called with the cloud path of every chunk the file needs, once per chunk,
+/// and must return a Promise resolving to the chunk file as a Uint8Array.
+/// The chunks are checked to be the requested ones, decompressed, and the assembled file
+/// is checked against its SHA1 hash before being returned.
+#[wasm_bindgen]
+pub async fn download_file(
+    manifest_bytes: Vec<u8>,
+    filename: String,
+    fetch: Function,
+) -> Result<Vec<u8>, JsValue> {
+    let manifest = FManifestParser::new(&manifest_bytes).parse().map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ))
+    })?;
+
+    let file = manifest
+        .find_file(&filename)
+        .ok_or_else(|| JsValue::from_str(&format!("File not found in manifest: {}", filename)))?;
+
+    let feature_level = manifest.meta.feature_level();
+    let mut chunk_data: HashMap<FGuid, Vec<u8>> = HashMap::new();
+
+    for part in file.chunk_parts() {
+        if chunk_data.contains_key(part.guid()) {
+            continue;
+        }
+
+        let chunk_info = manifest
+            .chunk_list
+            .find_by_guid(part.guid())
+            .ok_or_else(|| {
+                JsValue::from_str(&format!(
+                    "Chunk {} is missing from the chunk list",
+                    part.guid().to_hex_lower()
+                ))
+            })?;
+        let cloud_path = chunk_info.cloud_path(feature_level);
+
+        let chunk_bytes = fetch_chunk(&fetch, &cloud_path).await?;
+        let chunk = FChunk::parse_verified(&chunk_bytes, part.guid()).map_err(|e| {
+            JsValue::from_str(&format!("Failed to parse chunk {}: {:?}", cloud_path, e))
+        })?;
+
+        chunk_data.insert(*part.guid(), chunk.data().to_vec());
+    }
+
+    assemble_file(file, &chunk_data)
+}
+
+/// Assembles a single file of the manifest from chunk files the caller already has.
+///
+/// `chunk_data_js` is an object mapping chunk GUIDs, as 32 hex characters in either case,
+/// to the chunk files as downloaded from the CDN. Only the chunks of `filename` are needed.
+/// The assembled file is checked against its SHA1 hash before being returned.
+#[wasm_bindgen]
+pub fn extract_file(
+    manifest_bytes: &[u8],
+    filename: &str,
+    chunk_data_js: JsValue,
+) -> Result<Vec<u8>, JsValue> {
+    let manifest = FManifestParser::new(manifest_bytes).parse().map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ))
+    })?;
+
+    let file = manifest
+        .find_file(filename)
+        .ok_or_else(|| JsValue::from_str(&format!("File not found in manifest: {}", filename)))?;
+
+    let mut chunk_data: HashMap<FGuid, Vec<u8>> = HashMap::new();
+
+    for part in file.chunk_parts() {
+        if chunk_data.contains_key(part.guid()) {
+            continue;
+        }
+
+        let guid = part.guid();
+        let mut value = Reflect::get(&chunk_data_js, &JsValue::from_str(&guid.to_hex_lower()))?;
+        if value.is_undefined() {
+            value = Reflect::get(&chunk_data_js, &JsValue::from_str(&guid.to_hex_upper()))?;
+        }
+
+        if !value.is_instance_of::<Uint8Array>() {
+            return Err(JsValue::from_str(&format!(
+                "No Uint8Array given for chunk {}",
+                guid.to_hex_lower()
+            )));
+        }
+
+        let chunk_bytes = Uint8Array::new(&value).to_vec();
+        let chunk = FChunk::parse_verified(&chunk_bytes, guid).map_err(|e| {
+            JsValue::from_str(&format!(
+                "Failed to parse chunk {}: {:?}",
+                guid.to_hex_lower(),
+                e
+            ))
+        })?;
+
+        chunk_data.insert(*guid, chunk.data().to_vec());
+    }
+
+    assemble_file(file, &chunk_data)
+}
+
+/// Assembles `file` from decompressed chunk data, failing if it doesn't match its hash
+fn assemble_file(
+    file: &FFileManifest,
+    chunk_data: &HashMap<FGuid, Vec<u8>>,
+) -> Result<Vec<u8>, JsValue> {
+    let (data, hash_matches) = file.reconstruct_and_verify(chunk_data).map_err(|e| {
+        JsValue::from_str(&format!("Failed to assemble {}: {:?}", file.filename(), e))
+    })?;
+
+    if !hash_matches {
+        return Err(JsValue::from_str(&format!(
+            "Hash of the assembled {} does not match the manifest",
+            file.filename()
+        )));
+    }
+
+    Ok(data)
+}
+
+/// Calls the JS `fetch` callback with `cloud_path` and waits for the chunk bytes it resolves to
+async fn fetch_chunk(fetch: &Function, cloud_path: &str) -> Result<Vec<u8>, JsValue> {
+    let promise: Promise = fetch
+        .call1(&JsValue::NULL, &JsValue::from_str(cloud_path))?
+        .dyn_into()
+        .map_err(|_| JsValue::from_str("fetch callback must return a Promise"))?;
+
+    let value = JsFuture::from(promise).await?;
+    if !value.is_instance_of::<Uint8Array>() {
+        return Err(JsValue::from_str(&format!(
+            "fetch callback must resolve to a Uint8Array, got something else for {}",
+            cloud_path
+        )));
+    }
+
+    Ok(Uint8Array::new(&value).to_vec())
+}
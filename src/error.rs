@@ -1,3 +1,4 @@
+use crate::manifest::shared::{EFeatureLevel, FGuid};
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -9,7 +10,16 @@ pub enum ParseError {
     DecompressionError,
     HashMismatch,
     SizeMismatch,
-    Overflow
+    Overflow,
+    InconsistentVersions(String),
+    HeaderMisaligned,
+    GuidMismatch,
+    RoundtripMismatch,
+    Io(std::io::Error),
+    /// The data of a chunk that is needed wasn't provided
+    MissingChunk(FGuid),
+    /// A chunk present in both chunk lists being merged, with a different SHA1 hash in each
+    ChunkConflict(FGuid),
 }
 
 impl std::fmt::Display for ParseError {
@@ -24,9 +34,139 @@ impl std::fmt::Display for ParseError {
             ParseError::DecompressionError => write!(f, "Decompression failed"),
             ParseError::HashMismatch => write!(f, "Hash does not match"),
             ParseError::SizeMismatch => write!(f, "Sizes does not match"),
+            ParseError::InconsistentVersions(reason) => write!(f, "Inconsistent versions: {}", reason),
+            ParseError::HeaderMisaligned => write!(f, "Header fields are misaligned"),
+            ParseError::GuidMismatch => write!(f, "GUID does not match"),
+            ParseError::RoundtripMismatch => write!(f, "Manifest changed when serialized and parsed again"),
+            ParseError::Io(e) => write!(f, "I/O error: {}", e),
+            ParseError::MissingChunk(guid) => write!(f, "Chunk {} is missing", guid.to_string()),
+            ParseError::ChunkConflict(guid) => write!(f, "Chunk {} has differing content in both chunk lists", guid.to_string()),
             
         }
     }
 }
 
-impl std::error::Error for ParseError {}
\ No newline at end of file
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A soft anomaly found while parsing, which didn't prevent the manifest from being parsed
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub enum ParseWarning {
+    /// A section didn't end where its size said it would, tolerated with `lenient_sizes`
+    SectionSizeMismatch {
+        section: &'static str,
+        expected: u32,
+        actual: usize,
+    },
+    /// Zero bytes after the manifest data, tolerated with `allow_trailing_padding`
+    TrailingPadding(usize),
+    /// The header reports the broken json version (255)
+    BrokenJsonVersion,
+    DuplicateFilenames(Vec<String>),
+    /// Chunks in the chunk list that no file references
+    OrphanChunks(usize),
+    /// A file whose SHA1 hash is all zeros
+    ZeroFileHash(String),
+    /// A section version newer than the ones the parser knows, read with the latest known layout
+    UnknownSectionVersion { section: &'static str, version: u8 },
+    /// Bytes after the custom fields, most likely a section added by a newer format.
+    /// Kept on the manifest with `keep_unknown_trailing`, dropped otherwise.
+    UnknownTrailingData(usize),
+    /// The feature level disagrees with the section versions or contents, fails the parse with `strict_versions`
+    InconsistentVersions(String),
+    /// The header predates the version field, the feature level was assumed to be this one
+    InferredFeatureLevel(EFeatureLevel),
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseWarning::SectionSizeMismatch {
+                section,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} size mismatch: expected {} but got {}",
+                section, expected, actual
+            ),
+            ParseWarning::TrailingPadding(size) => {
+                write!(f, "{} bytes of zero padding after the manifest data", size)
+            }
+            ParseWarning::BrokenJsonVersion => write!(f, "Broken json version"),
+            ParseWarning::DuplicateFilenames(filenames) => {
+                write!(f, "Duplicate file entries: {}", filenames.join(", "))
+            }
+            ParseWarning::OrphanChunks(count) => {
+                write!(f, "{} chunks aren't referenced by any file", count)
+            }
+            ParseWarning::ZeroFileHash(filename) => {
+                write!(f, "File {} has an empty hash", filename)
+            }
+            ParseWarning::UnknownSectionVersion { section, version } => {
+                write!(f, "{} has the unknown version {}", section, version)
+            }
+            ParseWarning::UnknownTrailingData(size) => {
+                write!(f, "{} unknown bytes after the custom fields", size)
+            }
+            ParseWarning::InconsistentVersions(reason) => {
+                write!(f, "Inconsistent versions: {}", reason)
+            }
+            ParseWarning::InferredFeatureLevel(version) => {
+                write!(f, "Header has no version field, assuming feature level {:?}", version)
+            }
+        }
+    }
+}
+
+/// The error type of the crate's high level API, covering both binary and JSON failures
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "Manifest error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(value: ParseError) -> Self {
+        Error::Parse(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
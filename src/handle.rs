@@ -0,0 +1,62 @@
+use wasm_bindgen::prelude::*;
+
+use crate::manifest::{FManifest, FManifestParser};
+
+/// A parsed manifest kept alive on the WASM side, so JS can parse once and query many times
+#[wasm_bindgen]
+pub struct ManifestHandle {
+    manifest: FManifest,
+}
+
+#[wasm_bindgen]
+impl ManifestHandle {
+    #[wasm_bindgen(constructor)]
+    pub fn new(manifest_bytes: &[u8]) -> Result<ManifestHandle, JsValue> {
+        let manifest = FManifestParser::new(manifest_bytes).parse().map_err(|e| {
+            JsValue::from_str(&format!(
+                "Failed to parse manifest (size: {} bytes): {:?}",
+                manifest_bytes.len(),
+                e
+            ))
+        })?;
+
+        Ok(ManifestHandle { manifest })
+    }
+
+    #[wasm_bindgen(js_name = appName)]
+    pub fn app_name(&self) -> String {
+        self.manifest.meta.app_name().to_owned()
+    }
+
+    #[wasm_bindgen(js_name = fileCount)]
+    pub fn file_count(&self) -> usize {
+        self.manifest.file_list.entries().len()
+    }
+
+    #[wasm_bindgen(js_name = fileNames)]
+    pub fn file_names(&self) -> Vec<String> {
+        self.manifest
+            .file_list
+            .entries()
+            .iter()
+            .map(|entry| entry.filename().to_owned())
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = chunkPlan)]
+    pub fn chunk_plan(&self, filename: &str) -> Result<JsValue, JsValue> {
+        let plan = self.manifest.file_chunk_plan(filename).ok_or_else(|| {
+            JsValue::from_str(&format!("File not found in manifest: {}", filename))
+        })?;
+
+        serde_wasm_bindgen::to_value(&plan)
+            .map_err(|e| JsValue::from_str(&format!("Failed to convert plan to JS: {:?}", e)))
+    }
+}
+
+impl ManifestHandle {
+    /// Returns the parsed manifest owned by this handle
+    pub fn manifest(&self) -> &FManifest {
+        &self.manifest
+    }
+}
@@ -26,4 +26,169 @@ where
     }
 
     result
-}
\ No newline at end of file
+}
+
+/// Matches `text` against a glob `pattern` supporting `*` (any run of characters, separators included)
+/// and `?` (exactly one character). Backslashes are treated as forward slashes on both sides.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.replace('\\', "/").chars().collect::<Vec<_>>();
+    let text = text.replace('\\', "/").chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` seen in the pattern, and the text position it was matched against
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+const fn build_crc_table_deprecated() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 0x80000000 != 0 {
+                (crc << 1) ^ 0x04C11DB7
+            } else {
+                crc << 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC_TABLE_DEPRECATED: [u32; 256] = build_crc_table_deprecated();
+
+/// Port of Unreal's FCrc::MemCrc_DEPRECATED, still used by BuildPatchServices for chunk group numbers
+pub fn mem_crc_deprecated(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = (crc << 8) ^ CRC_TABLE_DEPRECATED[((crc >> 24) ^ *byte as u32) as usize];
+    }
+    !crc
+}
+
+/// Splits a command line into arguments the way Windows programs do (`CommandLineToArgvW`):
+/// whitespace separates arguments unless quoted, `\"` is a literal quote, `""` inside quotes is
+/// a literal quote, and backslashes are only special right before a quote.
+pub fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Whether `current` is an argument, even if empty (as with `""`)
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut chars = command_line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.peek() == Some(&'\\') {
+                    chars.next();
+                    backslashes += 1;
+                }
+
+                if chars.peek() == Some(&'"') {
+                    // 2n backslashes before a quote are n backslashes, 2n+1 also escape the quote
+                    current.push_str(&"\\".repeat(backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        chars.next();
+                        current.push('"');
+                    }
+                } else {
+                    current.push_str(&"\\".repeat(backslashes));
+                }
+                in_arg = true;
+            }
+            '"' => {
+                if in_quotes && chars.peek() == Some(&'"') {
+                    chars.next();
+                    current.push('"');
+                } else {
+                    in_quotes = !in_quotes;
+                }
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_star_matches_any_run_of_characters() {
+        assert!(glob_match("*.pak", "Content/Paks/pakchunk0.pak"));
+        assert!(glob_match("*.pak", ".pak"));
+        assert!(!glob_match("*.pak", "Content/Paks/pakchunk0.sig"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_a_single_character() {
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file.txt"));
+        assert!(!glob_match("file?.txt", "file12.txt"));
+    }
+
+    #[test]
+    fn glob_directory_prefix_normalizes_separators() {
+        let pattern = "Engine/Binaries/**";
+        assert!(glob_match(pattern, "Engine\\Binaries\\Win64\\a.exe"));
+        assert!(glob_match("Engine\\Binaries\\*", "Engine/Binaries/a.exe"));
+        assert!(!glob_match(pattern, "Engine/Content/Binaries/a"));
+    }
+
+    #[test]
+    fn split_command_line_keeps_quoted_arguments_together() {
+        assert_eq!(
+            split_command_line(r#"-log "C:\Program Files\Game" -name="a b""#),
+            vec!["-log", r"C:\Program Files\Game", "-name=a b"]
+        );
+    }
+
+    #[test]
+    fn split_command_line_escapes_quotes() {
+        assert_eq!(
+            split_command_line(r#"a\"b "c""d" "" e\\"#),
+            vec![r#"a"b"#, r#"c"d"#, "", r"e\\"]
+        );
+        assert_eq!(split_command_line(r#"x\\"y z""#), vec![r"x\y z"]);
+    }
+}
@@ -1,7 +1,10 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde_json;
 use wasm_bindgen::prelude::*;
 
+pub mod download;
 pub mod error;
+pub mod handle;
 pub mod helper;
 pub mod manifest;
 pub mod reader;
@@ -41,22 +44,229 @@ pub fn parse_manifest(manifest_bytes: &[u8]) -> String {
 }
 
 #[wasm_bindgen]
-pub fn create_manifest(json_string: &str) -> Vec<u8> {
-    match serde_json::from_str::<manifest::FManifest>(json_string) {
-        Ok(manifest) => match manifest.serialize() {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                // Return empty vector on serialization error
-                // In a real implementation, you might want to handle this differently
-                eprintln!("Failed to serialize manifest: {:?}", e);
-                Vec::new()
-            }
+pub fn create_manifest(json_string: &str) -> Result<Vec<u8>, JsValue> {
+    create_manifest_checked(json_string)
+        .map_err(|e| JsValue::from_str(&format!("Failed to create manifest: {}", e)))
+}
+
+/// Parses binary manifest data into its JSON representation
+pub fn parse_manifest_checked(manifest_bytes: &[u8]) -> Result<String, error::Error> {
+    manifest::to_json(&manifest::parse(manifest_bytes)?)
+}
+
+/// Reads a manifest file from disk and returns its JSON representation
+pub fn parse_manifest_file_checked<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<String, error::Error> {
+    parse_manifest_checked(&std::fs::read(path)?)
+}
+
+/// Creates binary manifest data from its JSON representation
+pub fn create_manifest_checked(json_string: &str) -> Result<Vec<u8>, error::Error> {
+    manifest::create(json_string)
+}
+
+#[wasm_bindgen]
+pub fn create_manifest_base64(json_string: &str) -> Result<String, JsValue> {
+    let bytes = create_manifest_checked(json_string)
+        .map_err(|e| JsValue::from_str(&format!("Failed to create manifest: {}", e)))?;
+
+    Ok(BASE64.encode(bytes))
+}
+
+#[wasm_bindgen]
+pub fn parse_manifest_base64(manifest_base64: &str) -> Result<String, JsValue> {
+    let manifest_bytes = BASE64
+        .decode(manifest_base64.trim())
+        .map_err(|e| JsValue::from_str(&format!("Invalid base64 manifest data: {}", e)))?;
+
+    parse_manifest_checked(&manifest_bytes).map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {}",
+            manifest_bytes.len(),
+            e
+        ))
+    })
+}
+
+#[wasm_bindgen]
+pub fn manifest_summary(manifest_bytes: &[u8]) -> String {
+    let parser = manifest::FManifestParser::new(manifest_bytes);
+    match parser.parse() {
+        Ok(parsed) => match serde_json::to_string_pretty(&parsed.summary()) {
+            Ok(json) => json,
+            Err(e) => format!("Failed to serialize to JSON: {:?}", e),
         },
+        Err(e) => format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ),
+    }
+}
+
+#[wasm_bindgen]
+pub fn manifest_chunks_jsonl(manifest_bytes: &[u8]) -> String {
+    let parser = manifest::FManifestParser::new(manifest_bytes);
+    let parsed = match parser.parse() {
+        Ok(parsed) => parsed,
         Err(e) => {
-            // Return empty vector on JSON parsing error
-            // In a real implementation, you might want to handle this differently
-            eprintln!("Failed to parse JSON: {:?}", e);
-            Vec::new()
+            return format!(
+                "Failed to parse manifest (size: {} bytes): {:?}",
+                manifest_bytes.len(),
+                e
+            )
+        }
+    };
+
+    // One FChunkInfo object per line
+    let mut lines = String::new();
+    for chunk in parsed.chunk_list.chunks() {
+        match serde_json::to_string(chunk) {
+            Ok(json) => {
+                lines.push_str(&json);
+                lines.push('\n');
+            }
+            Err(e) => return format!("Failed to serialize to JSON: {:?}", e),
+        }
+    }
+
+    lines
+}
+
+#[wasm_bindgen]
+pub fn manifest_version_info(manifest_bytes: &[u8]) -> Result<JsValue, JsValue> {
+    // Only the header and meta are needed, the chunk and file lists are skipped
+    let (header, meta) = manifest::parse_meta_only(manifest_bytes).map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ))
+    })?;
+    let info = manifest::summary::VersionInfo::new(&header, &meta);
+
+    serde_wasm_bindgen::to_value(&info)
+        .map_err(|e| JsValue::from_str(&format!("Failed to convert version info to JS: {:?}", e)))
+}
+
+#[wasm_bindgen]
+pub fn manifest_group_histogram(manifest_bytes: &[u8]) -> Result<Vec<u32>, JsValue> {
+    let parser = manifest::FManifestParser::new(manifest_bytes);
+    let parsed = parser.parse().map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ))
+    })?;
+
+    Ok(parsed.group_histogram().to_vec())
+}
+
+#[wasm_bindgen]
+pub fn file_chunk_plan(manifest_bytes: &[u8], filename: &str) -> Result<JsValue, JsValue> {
+    let parser = manifest::FManifestParser::new(manifest_bytes);
+    let parsed = parser.parse().map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to parse manifest (size: {} bytes): {:?}",
+            manifest_bytes.len(),
+            e
+        ))
+    })?;
+
+    let plan = parsed
+        .file_chunk_plan(filename)
+        .ok_or_else(|| JsValue::from_str(&format!("File not found in manifest: {}", filename)))?;
+
+    serde_wasm_bindgen::to_value(&plan)
+        .map_err(|e| JsValue::from_str(&format!("Failed to convert plan to JS: {:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::fixtures;
+
+    #[test]
+    fn chunks_jsonl_has_one_json_object_per_chunk() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijklmn")]);
+        let bytes = manifest.serialize().unwrap();
+
+        let jsonl = manifest_chunks_jsonl(&bytes);
+
+        let lines = jsonl.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), manifest.chunk_list.chunks().len());
+        for line in lines {
+            let chunk: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(chunk.is_object());
         }
     }
+
+    #[test]
+    fn checked_functions_round_trip_through_json() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+
+        let json = parse_manifest_checked(&bytes).unwrap();
+
+        assert_eq!(create_manifest_checked(&json).unwrap(), bytes);
+    }
+
+    #[test]
+    fn manifests_round_trip_through_base64() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+        let json = parse_manifest_checked(&bytes).unwrap();
+
+        let base64 = create_manifest_base64(&json).unwrap();
+
+        assert_eq!(BASE64.decode(&base64).unwrap(), bytes);
+        assert_eq!(
+            parse_manifest_base64(&format!("{}\n", base64)).unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn invalid_manifests_are_parse_errors() {
+        let result = parse_manifest_checked(b"not a manifest");
+
+        assert!(matches!(result, Err(error::Error::Parse(_))));
+    }
+
+    #[test]
+    fn invalid_json_is_a_json_error() {
+        let result = create_manifest_checked("{ not json");
+
+        assert!(matches!(result, Err(error::Error::Json(_))));
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        use std::error::Error as _;
+
+        let result = parse_manifest_file_checked("does/not/exist.manifest");
+
+        let error = result.unwrap_err();
+        assert!(matches!(&error, error::Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound));
+        assert!(error.source().is_some());
+        assert!(error.to_string().starts_with("I/O error: "));
+    }
+
+    #[test]
+    fn manifest_files_are_read_from_disk() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+        let path = std::env::temp_dir().join("parse_manifest_file_checked.manifest");
+        std::fs::write(&path, &bytes).unwrap();
+
+        let json = parse_manifest_file_checked(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(json.unwrap(), parse_manifest_checked(&bytes).unwrap());
+    }
 }
@@ -0,0 +1,342 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::ParseResult;
+
+use super::{
+    chunk_info::FChunkInfo,
+    chunk_list::FChunkList,
+    chunk_part::FChunkPart,
+    chunks::FChunk,
+    custom_fields::FCustomFields,
+    file_manifest::FFileManifest,
+    file_manifest_list::FFileManifestList,
+    header::{FManifestHeader, MANIFEST_MAGIC},
+    meta::FManifestMeta,
+    rolling_hash::{self, FRollingHash},
+    shared::{EFeatureLevel, EManifestStorageFlags, FGuid, FSHAHash},
+    FManifest,
+};
+
+/// The chunk window size Epic uses by default
+pub const DEFAULT_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// How many trailing bytes the content defined chunker hashes when looking for a boundary
+const BOUNDARY_WINDOW_SIZE: u32 = 64;
+
+/// How the ManifestBuilder splits file data into chunks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkingStrategy {
+    /// Split files into chunks of the given size, Epic uses DEFAULT_CHUNK_SIZE
+    FixedSize(u32),
+    /// Variable size chunks, cut where the rolling hash of the last bytes matches a boundary pattern.
+    /// Chunks average around `target_size` and are never larger than `max_size`,
+    /// nor smaller than `min_size` unless they end a file.
+    ContentDefined {
+        min_size: u32,
+        target_size: u32,
+        max_size: u32,
+    },
+}
+
+impl ChunkingStrategy {
+    /// Returns whether manifests using this strategy need variable size chunk support
+    pub fn needs_variable_chunks(&self) -> bool {
+        match self {
+            ChunkingStrategy::FixedSize(size) => *size != DEFAULT_CHUNK_SIZE,
+            ChunkingStrategy::ContentDefined { .. } => true,
+        }
+    }
+
+    /// Splits `data` into the ranges that make up each chunk
+    pub fn split(&self, data: &[u8]) -> Vec<Range<usize>> {
+        match *self {
+            ChunkingStrategy::FixedSize(size) => {
+                let size = size.max(1) as usize;
+                (0..data.len())
+                    .step_by(size)
+                    .map(|start| start..(start + size).min(data.len()))
+                    .collect()
+            }
+            ChunkingStrategy::ContentDefined {
+                min_size,
+                target_size,
+                max_size,
+            } => {
+                let boundary_mask = target_size.max(1).next_power_of_two() as u64 - 1;
+                let mut hasher = FRollingHash::new(BOUNDARY_WINDOW_SIZE);
+                let mut ranges = Vec::new();
+                let mut start = 0;
+
+                for (i, byte) in data.iter().enumerate() {
+                    hasher.consume_byte(*byte);
+
+                    let length = (i + 1 - start) as u32;
+                    let at_boundary = length >= min_size
+                        && hasher.is_full()
+                        && hasher.hash() & boundary_mask == 0;
+
+                    if at_boundary || length >= max_size {
+                        ranges.push(start..i + 1);
+                        start = i + 1;
+                        hasher.clear();
+                    }
+                }
+
+                if start < data.len() {
+                    ranges.push(start..data.len());
+                }
+
+                ranges
+            }
+        }
+    }
+}
+
+/// Builds a new manifest and its chunk data from raw files
+#[derive(Debug, Clone, Default)]
+pub struct ManifestBuilder {
+    app_id: u32,
+    app_name: String,
+    build_version: String,
+    launch_exe: String,
+    launch_command: String,
+    chunks: Vec<FChunkInfo>,
+    chunk_data: HashMap<FGuid, Vec<u8>>,
+    files: Vec<FFileManifest>,
+    uses_variable_chunks: bool,
+}
+
+impl ManifestBuilder {
+    pub fn new(app_name: &str, build_version: &str) -> ManifestBuilder {
+        ManifestBuilder {
+            app_name: app_name.to_owned(),
+            build_version: build_version.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn app_id(mut self, app_id: u32) -> ManifestBuilder {
+        self.app_id = app_id;
+        self
+    }
+
+    pub fn launch(mut self, launch_exe: &str, launch_command: &str) -> ManifestBuilder {
+        self.launch_exe = launch_exe.to_owned();
+        self.launch_command = launch_command.to_owned();
+        self
+    }
+
+    /// Chunks `data` with the given strategy and adds it to the manifest as `filename`.
+    /// Chunks with identical content are shared between files.
+    pub fn add_file(
+        &mut self,
+        filename: &str,
+        data: &[u8],
+        strategy: ChunkingStrategy,
+    ) -> ParseResult<()> {
+        if strategy.needs_variable_chunks() {
+            self.uses_variable_chunks = true;
+        }
+
+        let mut chunk_parts = Vec::new();
+        for range in strategy.split(data) {
+            let guid = self.add_chunk(&data[range.clone()])?;
+            chunk_parts.push(FChunkPart::new(
+                guid,
+                0,
+                range.len() as u32,
+                range.start as u64,
+            ));
+        }
+
+        self.files.push(FFileManifest {
+            filename: filename.to_owned(),
+            hash: FSHAHash::new_from_hashable(data),
+            chunk_parts,
+            file_size: data.len() as u64,
+            ..Default::default()
+        });
+
+        Ok(())
+    }
+
+    /// Adds a chunk unless one with the same content exists, and returns its GUID
+    fn add_chunk(&mut self, data: &[u8]) -> ParseResult<FGuid> {
+        let sha_hash = FSHAHash::new_from_hashable(data);
+
+        // Derive the GUID from the content so identical chunks are deduplicated
+        let sha_data = sha_hash.data();
+        let component =
+            |i: usize| u32::from_le_bytes(sha_data[i * 4..i * 4 + 4].try_into().unwrap());
+        let guid = FGuid {
+            a: component(0),
+            b: component(1),
+            c: component(2),
+            d: component(3),
+        };
+
+        if self.chunk_data.contains_key(&guid) {
+            return Ok(guid);
+        }
+
+        let mut chunk_info = FChunkInfo {
+            guid,
+            hash: rolling_hash::get_hash_for_data_set(data),
+            sha_hash,
+            group_num: FChunkInfo::compute_group_num(&guid),
+            uncompressed_size: data.len() as u32,
+            compressed_size: 0,
+        };
+        chunk_info.compressed_size =
+            FChunk::new(&chunk_info, data.to_vec()).serialize()?.len() as i64;
+
+        self.chunks.push(chunk_info);
+        self.chunk_data.insert(guid, data.to_vec());
+
+        Ok(guid)
+    }
+
+    /// Returns the manifest along with the decompressed data of every chunk it references.
+    ///
+    /// The feature level is `UsesBuildTimeGeneratedBuildId` if variable size chunks were used,
+    /// and `StoredAsBinaryData` (the first binary level, fixed 1MiB chunks) otherwise.
+    pub fn build(self) -> (FManifest, HashMap<FGuid, Vec<u8>>) {
+        let feature_level = if self.uses_variable_chunks {
+            EFeatureLevel::UsesBuildTimeGeneratedBuildId
+        } else {
+            EFeatureLevel::StoredAsBinaryData
+        };
+
+        let build_id = FSHAHash::new_from_hashable(format!(
+            "{}{}{}",
+            self.app_id, self.app_name, self.build_version
+        ))
+        .to_hex_string();
+
+        let meta = FManifestMeta {
+            _size: 0,
+            _data_version: 2,
+            feature_level,
+            b_is_file_data: false,
+            app_id: self.app_id,
+            app_name: self.app_name,
+            build_version: self.build_version,
+            launch_exe: self.launch_exe,
+            launch_command: self.launch_command,
+            prerequisites: vec![],
+            prereq_name: String::new(),
+            prereq_path: String::new(),
+            prereq_args: String::new(),
+            build_id: Some(build_id),
+            prereq_ids: vec![],
+            uninstall_action_path: None,
+            uninstall_action_args: None,
+        };
+
+        let manifest = FManifest {
+            header: FManifestHeader::new(
+                MANIFEST_MAGIC,
+                0,
+                0,
+                0,
+                FSHAHash::default(),
+                EManifestStorageFlags::Compressed,
+                feature_level,
+            ),
+            meta,
+            chunk_list: FChunkList {
+                _manifest_version: feature_level,
+                _size: 0,
+                _version: 0,
+                chunks: self.chunks,
+            },
+            file_list: FFileManifestList {
+                _version: 0,
+                _size: 0,
+                _count: self.files.len() as u32,
+                entries: self.files,
+            },
+            custom_fields: FCustomFields::default(),
+            data: vec![],
+            unknown_trailing: vec![],
+            compressed_body: None,
+        };
+
+        (manifest, self.chunk_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::fixtures;
+
+    /// Deterministic data without repeating patterns, so content defined boundaries vary
+    fn pseudo_random_data(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    /// Parses `manifest` back and reassembles `filename`, failing if its hash doesn't match
+    fn reconstruct(
+        manifest: &FManifest,
+        chunk_data: &HashMap<FGuid, Vec<u8>>,
+        filename: &str,
+    ) -> Vec<u8> {
+        let manifest = fixtures::reparsed(manifest);
+        let file = manifest.find_file(filename).unwrap();
+
+        let (data, hash_matches) = file.reconstruct_and_verify(chunk_data).unwrap();
+        assert!(hash_matches);
+        data
+    }
+
+    #[test]
+    fn fixed_size_chunks_reconstruct_the_file() {
+        let data = pseudo_random_data(DEFAULT_CHUNK_SIZE as usize * 5 / 2);
+        let mut builder = ManifestBuilder::new("TestApp", "1.0.0");
+        let strategy = ChunkingStrategy::FixedSize(DEFAULT_CHUNK_SIZE);
+        builder.add_file("a.bin", &data, strategy).unwrap();
+
+        let (manifest, chunk_data) = builder.build();
+
+        assert_eq!(
+            manifest.meta.feature_level(),
+            EFeatureLevel::StoredAsBinaryData
+        );
+        assert_eq!(manifest.chunk_list.chunks().len(), 3);
+        assert_eq!(reconstruct(&manifest, &chunk_data, "a.bin"), data);
+    }
+
+    #[test]
+    fn content_defined_chunks_reconstruct_the_file() {
+        let data = pseudo_random_data(16 * 1024);
+        let strategy = ChunkingStrategy::ContentDefined {
+            min_size: 128,
+            target_size: 512,
+            max_size: 2048,
+        };
+        let mut builder = ManifestBuilder::new("TestApp", "1.0.0");
+        builder.add_file("a.bin", &data, strategy).unwrap();
+
+        let (manifest, chunk_data) = builder.build();
+
+        assert_eq!(
+            manifest.meta.feature_level(),
+            EFeatureLevel::UsesBuildTimeGeneratedBuildId
+        );
+        let parts = manifest.find_file("a.bin").unwrap().chunk_parts();
+        let sizes = parts.iter().map(|part| part.size()).collect::<Vec<_>>();
+        let (last, others) = sizes.split_last().unwrap();
+        assert!(others.iter().all(|size| (128..=2048).contains(size)));
+        assert!(*last <= 2048);
+        // Not every chunk was cut at the maximum size
+        assert!(others.iter().any(|size| *size < 2048));
+        assert_eq!(reconstruct(&manifest, &chunk_data, "a.bin"), data);
+    }
+}
@@ -2,9 +2,10 @@ use core::fmt;
 
 use crate::helper;
 
-use super::shared::{FGuid, FSHAHash};
+use super::shared::{EFeatureLevel, FGuid, FSHAHash};
 
 #[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FChunkInfo {
     pub(crate) guid: FGuid,
     pub(crate) hash: u64,
@@ -39,6 +40,16 @@ impl fmt::Debug for FChunkInfo {
 }
 
 impl FChunkInfo {
+    /// Computes the group number of a chunk the same way Unreal does, for manifests that don't store it
+    pub fn compute_group_num(guid: &FGuid) -> u8 {
+        let mut guid_bytes = Vec::with_capacity(16);
+        for component in [guid.a, guid.b, guid.c, guid.d] {
+            guid_bytes.extend_from_slice(&component.to_le_bytes());
+        }
+
+        (helper::mem_crc_deprecated(&guid_bytes) % 100) as u8
+    }
+
     pub fn guid(&self) -> &FGuid {
         &self.guid
     }
@@ -81,6 +92,39 @@ impl FChunkInfo {
     pub fn compressed_size(&self) -> i64 {
         self.compressed_size
     }
+
+    /// Updates the hashes of the chunk, e.g. after its data was rewritten
+    pub fn set_hashes(&mut self, hash: u64, sha_hash: FSHAHash) {
+        self.hash = hash;
+        self.sha_hash = sha_hash;
+    }
+
+    /// Updates the sizes of the chunk, e.g. after it was recompressed
+    pub fn set_sizes(&mut self, uncompressed_size: u32, compressed_size: i64) {
+        self.uncompressed_size = uncompressed_size;
+        self.compressed_size = compressed_size;
+    }
+
+    /// Returns the directory of this chunk relative to the CDN base url, e.g. `ChunksV4/05`
+    pub fn cloud_dir(&self, feature_level: EFeatureLevel) -> String {
+        format!("{}/{}", feature_level.chunk_sub_dir(), self.group_num_str())
+    }
+
+    /// Returns the file name of this chunk on the CDN, e.g. `0123456789ABCDEF_<guid>.chunk`.
+    /// The CDN expects both the hash and the GUID in uppercase.
+    pub fn chunk_filename(&self) -> String {
+        format!("{}_{}.chunk", self.hash_str(), self.guid.to_hex_upper())
+    }
+
+    /// Returns the path of this chunk relative to the CDN base url, e.g. `ChunksV4/05/0123456789ABCDEF_<guid>.chunk`.
+    /// Use `cloud_dir` and `chunk_filename` to lay chunks out under another base path.
+    pub fn cloud_path(&self, feature_level: EFeatureLevel) -> String {
+        format!(
+            "{}/{}",
+            self.cloud_dir(feature_level),
+            self.chunk_filename()
+        )
+    }
 }
 
 // Add ByteWritable implementation for FChunkInfo
@@ -96,3 +140,35 @@ impl ByteWritable for FChunkInfo {
         writer.write(&self.compressed_size);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cloud_paths_of_a_known_chunk() {
+        let chunk = FChunkInfo {
+            guid: FGuid {
+                a: 0x01234567,
+                b: 0x89ABCDEF,
+                c: 0xDEADBEEF,
+                d: 0x0BADF00D,
+            },
+            hash: 0x00AB_CDEF_0123_4567,
+            group_num: 5,
+            ..Default::default()
+        };
+
+        let filename = "00ABCDEF01234567_0123456789ABCDEFDEADBEEF0BADF00D.chunk";
+        assert_eq!(chunk.chunk_filename(), filename);
+        assert_eq!(chunk.cloud_dir(EFeatureLevel::Latest), "ChunksV4/05");
+        assert_eq!(
+            chunk.cloud_path(EFeatureLevel::Latest),
+            format!("ChunksV4/05/{}", filename)
+        );
+        assert_eq!(
+            chunk.cloud_path(EFeatureLevel::StoresChunkFileSizes),
+            format!("ChunksV3/05/{}", filename)
+        );
+    }
+}
@@ -1,13 +1,24 @@
-use crate::{error::ParseError, manifest::shared::FGuid, reader::ByteReader, ParseResult};
+use std::collections::HashMap;
 
-use super::{chunk_info::FChunkInfo, shared::EFeatureLevel};
+use crate::{
+    error::{ParseError, ParseWarning},
+    manifest::shared::FGuid,
+    reader::ByteReader,
+    ParseResult,
+};
+
+use super::{
+    chunk_info::FChunkInfo, chunks::chunk_header::LEGACY_CHUNK_WINDOW_SIZE, shared::EFeatureLevel,
+    ParseOptions,
+};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FChunkList {
-    _manifest_version: EFeatureLevel,
-    _size: u32,
-    _version: u8,
-    chunks: Vec<FChunkInfo>,
+    pub(crate) _manifest_version: EFeatureLevel,
+    pub(crate) _size: u32,
+    pub(crate) _version: u8,
+    pub(crate) chunks: Vec<FChunkInfo>,
 }
 
 impl FChunkList {
@@ -15,6 +26,8 @@ impl FChunkList {
     pub fn parse(
         reader: &mut ByteReader,
         manifest_version: EFeatureLevel,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
     ) -> ParseResult<FChunkList> {
         let reader_start = reader.tell();
 
@@ -22,6 +35,17 @@ impl FChunkList {
         let version = reader.read()?;
         let count: u32 = reader.read()?;
 
+        let stores_sha_hashes = stores_sha_hashes(manifest_version);
+        let stores_group_numbers = stores_group_numbers(manifest_version);
+        let stores_file_sizes = stores_file_sizes(manifest_version);
+
+        // guid, hash, sha, group, uncompressed and compressed size
+        let sha_hash_size = if stores_sha_hashes { 20 } else { 0 };
+        let group_num_size = if stores_group_numbers { 1 } else { 0 };
+        let file_sizes_size = if stores_file_sizes { 4 + 8 } else { 0 };
+        let min_chunk_size = 16 + 8 + sha_hash_size + group_num_size + file_sizes_size;
+        reader.check_count(count as usize, min_chunk_size)?;
+
         let mut chunks: Vec<FChunkInfo> = vec![Default::default(); count as usize];
 
         for chunk in chunks.iter_mut() {
@@ -32,20 +56,38 @@ impl FChunkList {
             chunk.hash = reader.read()?;
         }
 
-        for chunk in chunks.iter_mut() {
-            chunk.sha_hash = reader.read()?;
+        // Older manifests don't store the SHA1 hash, it is left zeroed
+        if stores_sha_hashes {
+            for chunk in chunks.iter_mut() {
+                chunk.sha_hash = reader.read()?;
+            }
         }
 
-        for chunk in chunks.iter_mut() {
-            chunk.group_num = reader.read()?;
+        // Older manifests don't store the group number, it has to be computed from the GUID
+        if stores_group_numbers {
+            for chunk in chunks.iter_mut() {
+                chunk.group_num = reader.read()?;
+            }
+        } else {
+            for chunk in chunks.iter_mut() {
+                chunk.group_num = FChunkInfo::compute_group_num(&chunk.guid);
+            }
         }
 
-        for chunk in chunks.iter_mut() {
-            chunk.uncompressed_size = reader.read()?;
-        }
+        // Older manifests don't store the sizes, their chunks always used the fixed 1MiB window
+        // and the compressed size is unknown, left at 0
+        if stores_file_sizes {
+            for chunk in chunks.iter_mut() {
+                chunk.uncompressed_size = reader.read()?;
+            }
 
-        for chunk in chunks.iter_mut() {
-            chunk.compressed_size = reader.read()?;
+            for chunk in chunks.iter_mut() {
+                chunk.compressed_size = reader.read()?;
+            }
+        } else {
+            for chunk in chunks.iter_mut() {
+                chunk.uncompressed_size = LEGACY_CHUNK_WINDOW_SIZE;
+            }
         }
 
         if reader_start + size as usize != reader.tell() {
@@ -55,7 +97,15 @@ impl FChunkList {
                 reader.tell() - reader_start,
                 version
             );
-            return Err(ParseError::InvalidData);
+            if !options.lenient_sizes {
+                return Err(ParseError::InvalidData);
+            }
+            warnings.push(ParseWarning::SectionSizeMismatch {
+                section: "ChunkList",
+                expected: size,
+                actual: reader.tell() - reader_start,
+            });
+            reader.seek(reader_start + size as usize);
         }
 
         Ok(FChunkList {
@@ -68,86 +118,316 @@ impl FChunkList {
 
     /// Writes the FChunkList to a ByteWriter
     pub fn write(&self, writer: &mut crate::writer::ByteWriter) {
-        use crate::writer::ByteWritable;
-
-        // Calculate the size first by writing to a temporary buffer
-        let mut temp_writer = crate::writer::ByteWriter::new();
-        temp_writer.write(&self._version);
-        temp_writer.write(&(self.chunks.len() as u32));
+        // Reserve the size field and back-patch it once the section is written
+        let start = writer.tell();
+        writer.write(&0u32);
+        writer.write(&self._version);
+        writer.write(&(self.chunks.len() as u32));
 
         // Write all GUIDs first
         for chunk in &self.chunks {
-            temp_writer.write(&chunk.guid);
+            writer.write(&chunk.guid);
         }
 
         // Write all hashes
         for chunk in &self.chunks {
-            temp_writer.write(&chunk.hash);
+            writer.write(&chunk.hash);
         }
 
-        // Write all SHA hashes
-        for chunk in &self.chunks {
-            temp_writer.write(&chunk.sha_hash);
+        // SHA hashes are only stored from StoresChunkDataShaHashes onwards
+        if stores_sha_hashes(self._manifest_version) {
+            for chunk in &self.chunks {
+                writer.write(&chunk.sha_hash);
+            }
         }
 
-        // Write all group numbers
-        for chunk in &self.chunks {
-            temp_writer.write(&chunk.group_num);
+        // Group numbers are only stored from StoresDataGroupNumbers onwards
+        if stores_group_numbers(self._manifest_version) {
+            for chunk in &self.chunks {
+                writer.write(&chunk.group_num);
+            }
         }
 
-        // Write all uncompressed sizes
-        for chunk in &self.chunks {
-            temp_writer.write(&chunk.uncompressed_size);
-        }
+        // Sizes are only stored from StoresChunkFileSizes onwards
+        if stores_file_sizes(self._manifest_version) {
+            // Write all uncompressed sizes
+            for chunk in &self.chunks {
+                writer.write(&chunk.uncompressed_size);
+            }
 
-        // Write all compressed sizes
-        for chunk in &self.chunks {
-            temp_writer.write(&chunk.compressed_size);
+            // Write all compressed sizes
+            for chunk in &self.chunks {
+                writer.write(&chunk.compressed_size);
+            }
         }
 
-        let size = (temp_writer.tell() + 4) as u32; // +4 for the size field itself
+        writer.write_u32_at(start, (writer.tell() - start) as u32); // the size includes the size field itself
+    }
 
-        // Write the actual data with correct size
-        writer.write(&size);
-        writer.write(&self._version);
-        writer.write(&(self.chunks.len() as u32));
+    /// Returns the union of both chunk lists, deduplicated by GUID, at the newer of both feature levels
+    /// so that no column of either list is dropped when serialized.
+    /// Fails if a GUID is present twice with a different SHA hash.
+    pub fn union(&self, other: &FChunkList) -> ParseResult<FChunkList> {
+        let mut chunks: Vec<FChunkInfo> =
+            Vec::with_capacity(self.chunks.len() + other.chunks.len());
+        let mut index_by_guid: HashMap<FGuid, usize> = HashMap::new();
 
-        // Write all GUIDs first
-        for chunk in &self.chunks {
-            writer.write(&chunk.guid);
+        for chunk in self.chunks.iter().chain(&other.chunks) {
+            match index_by_guid.get(chunk.guid()) {
+                Some(&index) if chunks[index].sha_hash() != chunk.sha_hash() => {
+                    return Err(ParseError::ChunkConflict(*chunk.guid()));
+                }
+                Some(_) => {}
+                None => {
+                    index_by_guid.insert(*chunk.guid(), chunks.len());
+                    chunks.push(chunk.clone());
+                }
+            }
         }
 
-        // Write all hashes
-        for chunk in &self.chunks {
-            writer.write(&chunk.hash);
-        }
+        let manifest_version =
+            if self._manifest_version.to_i32() >= other._manifest_version.to_i32() {
+                self._manifest_version
+            } else {
+                other._manifest_version
+            };
 
-        // Write all SHA hashes
-        for chunk in &self.chunks {
-            writer.write(&chunk.sha_hash);
-        }
+        Ok(FChunkList {
+            _manifest_version: manifest_version,
+            _size: 0,
+            _version: self._version.max(other._version),
+            chunks,
+        })
+    }
 
-        // Write all group numbers
-        for chunk in &self.chunks {
-            writer.write(&chunk.group_num);
-        }
+    pub fn find_by_guid(&self, guid: &FGuid) -> Option<&FChunkInfo> {
+        self.chunks.iter().find(|chunk| chunk.guid() == guid)
+    }
 
-        // Write all uncompressed sizes
-        for chunk in &self.chunks {
-            writer.write(&chunk.uncompressed_size);
-        }
+    /// Returns the position of the chunk with the given GUID, to be used with `get_mut`
+    pub fn index_of_guid(&self, guid: &FGuid) -> Option<usize> {
+        self.chunks.iter().position(|chunk| chunk.guid() == guid)
+    }
 
-        // Write all compressed sizes
-        for chunk in &self.chunks {
-            writer.write(&chunk.compressed_size);
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut FChunkInfo> {
+        self.chunks.get_mut(index)
+    }
+
+    /// Sorts the chunks by GUID, giving a chunk order that doesn't depend on how the list was edited
+    pub fn sort_by_guid(&mut self) {
+        self.chunks.sort_by_key(|chunk| chunk.guid);
+    }
+
+    pub fn total_uncompressed_size(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.uncompressed_size() as u64)
+            .sum()
+    }
+
+    /// Negative compressed sizes (which would be corrupt data) count as zero
+    pub fn total_compressed_size(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.compressed_size().max(0) as u64)
+            .sum()
+    }
+
+    /// Returns how many bytes `write` produces, without writing anything
+    pub fn write_size(&self) -> usize {
+        let mut chunk_size = 16 + 8;
+        if stores_sha_hashes(self._manifest_version) {
+            chunk_size += 20;
+        }
+        if stores_group_numbers(self._manifest_version) {
+            chunk_size += 1;
+        }
+        if stores_file_sizes(self._manifest_version) {
+            chunk_size += 4 + 8;
         }
+
+        // size, version and count
+        4 + 1 + 4 + self.chunks.len() * chunk_size
     }
 
-    pub fn find_by_guid(&self, guid: &FGuid) -> Option<&FChunkInfo> {
-        self.chunks.iter().find(|chunk| chunk.guid() == guid)
+    /// Returns the version of the chunk list section, unrelated to the feature level
+    pub fn version(&self) -> u8 {
+        self._version
     }
 
     pub fn chunks(&self) -> &Vec<FChunkInfo> {
         &self.chunks
     }
 }
+
+/// SHA1 hashes are only stored from `StoresChunkDataShaHashes` onwards
+fn stores_sha_hashes(manifest_version: EFeatureLevel) -> bool {
+    manifest_version.effective().to_i32() >= EFeatureLevel::StoresChunkDataShaHashes.to_i32()
+}
+
+/// Group numbers are only stored from `StoresDataGroupNumbers` onwards
+fn stores_group_numbers(manifest_version: EFeatureLevel) -> bool {
+    manifest_version.effective().to_i32() >= EFeatureLevel::StoresDataGroupNumbers.to_i32()
+}
+
+/// Uncompressed and compressed sizes are only stored from `StoresChunkFileSizes` onwards
+fn stores_file_sizes(manifest_version: EFeatureLevel) -> bool {
+    manifest_version.effective().to_i32() >= EFeatureLevel::StoresChunkFileSizes.to_i32()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manifest::{
+        chunk_info::FChunkInfo,
+        chunks::chunk_header::LEGACY_CHUNK_WINDOW_SIZE,
+        fixtures,
+        shared::{EFeatureLevel, FSHAHash},
+        FManifest,
+    };
+
+    /// Parses `manifest` back at `level`, after giving its first chunk a group number no GUID maps to
+    fn reparsed_with_custom_group(manifest: &FManifest, level: EFeatureLevel) -> FManifest {
+        let mut manifest = manifest.clone();
+        fixtures::set_feature_level(&mut manifest, level);
+        let chunk = &mut manifest.chunk_list.chunks[0];
+        chunk.group_num = FChunkInfo::compute_group_num(chunk.guid()).wrapping_add(1);
+
+        fixtures::reparsed(&manifest)
+    }
+
+    #[test]
+    fn chunks_can_be_edited_after_a_guid_lookup() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let guid = *manifest.chunk_list.chunks()[1].guid();
+        let other_size = manifest.chunk_list.chunks()[0].compressed_size();
+
+        let index = manifest.chunk_list.index_of_guid(&guid).unwrap();
+        let chunk = manifest.chunk_list.get_mut(index).unwrap();
+        chunk.set_sizes(4, 12);
+
+        let chunk = manifest.chunk_list.find_by_guid(&guid).unwrap();
+        assert_eq!(chunk.compressed_size(), 12);
+        assert_eq!(
+            manifest.chunk_list.chunks()[0].compressed_size(),
+            other_size
+        );
+        assert!(manifest.chunk_list.get_mut(2).is_none());
+    }
+
+    #[test]
+    fn group_numbers_are_computed_below_stores_data_group_numbers() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let computed = FChunkInfo::compute_group_num(manifest.chunk_list.chunks()[0].guid());
+
+        let below = reparsed_with_custom_group(&manifest, EFeatureLevel::StoresIfChunkOrFileData);
+        let at = reparsed_with_custom_group(&manifest, EFeatureLevel::StoresDataGroupNumbers);
+
+        assert_eq!(below.chunk_list.chunks()[0].group_num(), computed);
+        assert_eq!(
+            at.chunk_list.chunks()[0].group_num(),
+            computed.wrapping_add(1)
+        );
+        // One group number byte per chunk
+        assert_eq!(at.chunk_list._size, below.chunk_list._size + 2);
+        assert_eq!(below.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[test]
+    fn total_sizes_match_a_manual_sum() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefghij")]);
+        let chunks = &mut manifest.chunk_list.chunks;
+        chunks[0].set_sizes(4, 10);
+        chunks[1].set_sizes(4, 12);
+        chunks[2].set_sizes(2, -1);
+
+        assert_eq!(manifest.chunk_list.total_uncompressed_size(), 4 + 4 + 2);
+        // The corrupt negative size counts as zero
+        assert_eq!(manifest.chunk_list.total_compressed_size(), 10 + 12);
+    }
+
+    #[test]
+    fn sha_hashes_are_only_stored_from_stores_chunk_data_sha_hashes() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let sha_hash = manifest.chunk_list.chunks()[0].sha_hash().clone();
+
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoredAsCompressedUClass);
+        let below = fixtures::reparsed(&manifest);
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoresChunkDataShaHashes);
+        let at = fixtures::reparsed(&manifest);
+
+        assert_eq!(
+            *below.chunk_list.chunks()[0].sha_hash(),
+            FSHAHash::default()
+        );
+        assert_eq!(*at.chunk_list.chunks()[0].sha_hash(), sha_hash);
+        // One 20 byte hash per chunk
+        assert_eq!(at.chunk_list._size, below.chunk_list._size + 2 * 20);
+        assert_eq!(below.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[test]
+    fn file_sizes_are_only_stored_from_stores_chunk_file_sizes() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let compressed_size = manifest.chunk_list.chunks()[0].compressed_size();
+
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoresPrerequisitesInfo);
+        let below = fixtures::reparsed(&manifest);
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoresChunkFileSizes);
+        let at = fixtures::reparsed(&manifest);
+
+        let below_chunk = &below.chunk_list.chunks()[0];
+        assert_eq!(below_chunk.uncompressed_size(), LEGACY_CHUNK_WINDOW_SIZE);
+        assert_eq!(below_chunk.compressed_size(), 0);
+        let at_chunk = &at.chunk_list.chunks()[0];
+        assert_eq!(at_chunk.uncompressed_size(), fixtures::CHUNK_SIZE);
+        assert_eq!(at_chunk.compressed_size(), compressed_size);
+        // A 4 byte uncompressed and an 8 byte compressed size per chunk
+        assert_eq!(at.chunk_list._size, below.chunk_list._size + 2 * 12);
+        assert_eq!(below.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[test]
+    fn union_dedupes_chunks_repeated_inside_the_overlay() {
+        let base = fixtures::manifest(&[("a.bin", b"aaaa")]);
+        let mut overlay = fixtures::manifest(&[("b.bin", b"bbbbcccc")]);
+        let repeated = overlay.chunk_list.chunks[1].clone();
+        overlay.chunk_list.chunks.push(repeated);
+
+        let union = base.chunk_list.union(&overlay.chunk_list).unwrap();
+
+        let guids = union
+            .chunks()
+            .iter()
+            .map(|chunk| *chunk.guid())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            guids,
+            vec![
+                *base.chunk_list.chunks()[0].guid(),
+                *overlay.chunk_list.chunks()[0].guid(),
+                *overlay.chunk_list.chunks()[1].guid(),
+            ]
+        );
+    }
+
+    #[test]
+    fn union_keeps_the_newer_feature_level() {
+        let base = fixtures::manifest(&[("a.bin", b"aaaa")]);
+        let mut overlay = fixtures::manifest(&[("b.bin", b"bbbb")]);
+        fixtures::set_feature_level(&mut overlay, EFeatureLevel::StoresIfChunkOrFileData);
+
+        let union = overlay.chunk_list.union(&base.chunk_list).unwrap();
+        assert_eq!(
+            union._manifest_version.to_i32(),
+            base.chunk_list._manifest_version.to_i32()
+        );
+
+        // Merging onto an older overlay keeps the SHA hashes of the base chunks
+        let merged = fixtures::reparsed(&FManifest::merge(&base, &overlay).unwrap());
+        let guid = base.chunk_list.chunks()[0].guid();
+        assert_eq!(
+            merged.chunk_list.find_by_guid(guid).unwrap().sha_hash(),
+            base.chunk_list.chunks()[0].sha_hash()
+        );
+    }
+}
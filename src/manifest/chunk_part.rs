@@ -1,18 +1,37 @@
-use crate::{error::ParseError, reader::ByteReader, ParseResult};
+use crate::{
+    error::{ParseError, ParseWarning},
+    reader::ByteReader,
+    ParseResult,
+};
 
-use super::shared::FGuid;
+use super::{shared::FGuid, ParseOptions};
 
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FChunkPart {
     size: u32,
     guid: FGuid,
     offset: u32,
-    file_offset: usize,
+    file_offset: u64,
 }
 
 impl FChunkPart {
+    pub fn new(guid: FGuid, offset: u32, size: u32, file_offset: u64) -> FChunkPart {
+        FChunkPart {
+            size,
+            guid,
+            offset,
+            file_offset,
+        }
+    }
+
     /// This function is used to parse FChunkPart from a ByteReader
-    pub fn parse(reader: &mut ByteReader, file_offset: usize) -> ParseResult<FChunkPart> {
+    pub fn parse(
+        reader: &mut ByteReader,
+        file_offset: u64,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> ParseResult<FChunkPart> {
         let start = reader.tell();
 
         let struct_size = reader.read::<u32>()?;
@@ -26,7 +45,15 @@ impl FChunkPart {
                 struct_size,
                 reader.tell() - start
             );
-            return Err(ParseError::SizeMismatch);
+            if !options.lenient_sizes {
+                return Err(ParseError::SizeMismatch);
+            }
+            warnings.push(ParseWarning::SectionSizeMismatch {
+                section: "ChunkPart",
+                expected: struct_size,
+                actual: reader.tell() - start,
+            });
+            reader.seek(start + struct_size as usize);
         }
 
         Ok(FChunkPart {
@@ -37,6 +64,17 @@ impl FChunkPart {
         })
     }
 
+    /// Returns the bytes of the decompressed chunk this part covers.
+    /// Fails if `offset + size` doesn't fit in 32 bits, as no chunk can be that large.
+    pub fn chunk_range(&self) -> ParseResult<std::ops::Range<usize>> {
+        let end = self
+            .offset
+            .checked_add(self.size)
+            .ok_or(ParseError::Overflow)?;
+
+        Ok(self.offset as usize..end as usize)
+    }
+
     /// Writes the FChunkPart to a ByteWriter
     pub fn write(&self, writer: &mut crate::writer::ByteWriter) {
         use crate::writer::ByteWritable;
@@ -56,7 +94,12 @@ impl FChunkPart {
         writer.write(&self.size);
     }
 
-    pub fn file_offset(&self) -> usize {
+    /// Returns how many bytes `write` produces: the struct size, GUID, offset and size
+    pub fn write_size(&self) -> usize {
+        4 + 16 + 4 + 4
+    }
+
+    pub fn file_offset(&self) -> u64 {
         self.file_offset
     }
 
@@ -72,3 +115,22 @@ impl FChunkPart {
         self.offset
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_range_covers_the_part() {
+        let part = FChunkPart::new(FGuid::default(), 4, 8, 0);
+
+        assert_eq!(part.chunk_range().unwrap(), 4..12);
+    }
+
+    #[test]
+    fn chunk_range_fails_when_offset_plus_size_overflows() {
+        let part = FChunkPart::new(FGuid::default(), u32::MAX - 3, 8, 0);
+
+        assert!(matches!(part.chunk_range(), Err(ParseError::Overflow)));
+    }
+}
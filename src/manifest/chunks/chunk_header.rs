@@ -1,9 +1,12 @@
 use std::io::Read;
 
-use crate::{manifest::shared::{EChunkHashFlags, EChunkStorageFlags, EChunkVersion, FGuid, FSHAHash}, reader::ByteReader, ParseResult};
+use crate::{error::ParseError, manifest::shared::{EChunkHashFlags, EChunkStorageFlags, EChunkVersion, FGuid, FSHAHash}, reader::ByteReader, writer::{ByteWritable, ByteWriter}, ParseResult};
 
 pub const CHUNK_MAGIC: u32 = 0xB1FE3AA2;
 
+/// The fixed window size of chunks written before the uncompressed size was stored in the header
+pub const LEGACY_CHUNK_WINDOW_SIZE: u32 = 1024 * 1024;
+
 #[derive(Debug, Default, Clone, serde::Serialize)]
 pub struct FChunkHeader {
     magic: u32,
@@ -31,7 +34,7 @@ impl FChunkHeader {
         let data_size_compressed = reader.read()?;
         let guid: FGuid = reader.read()?;
         let rolling_hash = reader.read()?;
-        let stored_as = EChunkStorageFlags::from(reader.read::<u8>()?);
+        let stored_as = EChunkStorageFlags::from_u8(reader.read::<u8>()?).ok_or(ParseError::InvalidStorageFlag)?;
 
         let mut chunk_header = FChunkHeader {
             magic,
@@ -49,10 +52,12 @@ impl FChunkHeader {
             chunk_header.sha_hash = reader.read::<FSHAHash>().ok();
 
             if let Some(hash_type) = reader.read::<u8>().ok() {
-                chunk_header.hash_type = Some(EChunkHashFlags::from(hash_type));
+                chunk_header.hash_type = Some(EChunkHashFlags::from_u8(hash_type).ok_or(ParseError::InvalidData)?);
             }
         }
 
+        // This is the last field of the header, even for variable size chunks (stored in ChunksV4):
+        // their window size is the uncompressed size, there is no separate window size field.
         if version.to_i32() >= EChunkVersion::StoresDataSizeUncompressed.to_i32() 
         {
             chunk_header.data_size_uncompressed = reader.read::<u32>().ok();
@@ -66,6 +71,81 @@ impl FChunkHeader {
         Ok(chunk_header)
     }
 
+    /// Creates a new FChunkHeader, header_size and data_size_compressed are calculated in write_with_data
+    pub fn new(
+        version: EChunkVersion,
+        guid: FGuid,
+        rolling_hash: u64,
+        stored_as: EChunkStorageFlags,
+        hash_type: Option<EChunkHashFlags>,
+        data_size_uncompressed: Option<u32>,
+        sha_hash: Option<FSHAHash>,
+    ) -> Self {
+        FChunkHeader {
+            magic: CHUNK_MAGIC,
+            version,
+            header_size: 0,
+            data_size_compressed: 0,
+            guid,
+            rolling_hash,
+            stored_as,
+            hash_type,
+            data_size_uncompressed,
+            sha_hash,
+        }
+    }
+
+    /// Creates a new FChunkHeader using `EChunkVersion::Latest`, with every optional field populated
+    pub fn new_latest(
+        guid: FGuid,
+        rolling_hash: u64,
+        stored_as: EChunkStorageFlags,
+        data_size_uncompressed: u32,
+        sha_hash: FSHAHash,
+    ) -> Self {
+        FChunkHeader::new(
+            EChunkVersion::Latest,
+            guid,
+            rolling_hash,
+            stored_as,
+            Some(EChunkHashFlags::Both),
+            Some(data_size_uncompressed),
+            Some(sha_hash),
+        )
+    }
+
+    /// Writes the FChunkHeader followed by the chunk data (which should already be compressed if needed)
+    pub fn write_with_data(&self, writer: &mut ByteWriter, chunk_data: &[u8]) {
+        self.write_header(writer, chunk_data.len() as u32);
+        writer.write_bytes(chunk_data);
+    }
+
+    /// Writes the fields in the order `parse` reads them, header_size is computed from the version
+    fn write_header(&self, writer: &mut ByteWriter, data_size_compressed: u32) {
+        // Everything after magic, version, header_size and data_size_compressed
+        let mut temp_writer = ByteWriter::new();
+        temp_writer.write(&self.guid);
+        temp_writer.write(&self.rolling_hash);
+        temp_writer.write(&self.stored_as);
+
+        if self.version.to_i32() >= EChunkVersion::StoresShaAndHashType.to_i32() {
+            temp_writer.write(&self.sha_hash.clone().unwrap_or_default());
+            temp_writer.write(&self.hash_type.unwrap_or_default());
+        }
+
+        if self.version.to_i32() >= EChunkVersion::StoresDataSizeUncompressed.to_i32() {
+            temp_writer.write(&self.data_size_uncompressed.unwrap_or_default());
+        }
+
+        let header_size = (temp_writer.tell() + 16) as u32; // +16 for the four leading u32 fields
+
+        writer.write(&self.magic);
+        writer.write(&self.version);
+        writer.write(&header_size);
+        writer.write(&data_size_compressed);
+        writer.write_bytes(temp_writer.as_bytes());
+    }
+
     pub fn magic(&self) -> u32 {
         self.magic
     }
@@ -102,6 +182,12 @@ impl FChunkHeader {
         self.data_size_uncompressed
     }
 
+    /// Returns the window size of the chunk, which is its uncompressed size.
+    /// Headers older than `StoresDataSizeUncompressed` always used a fixed 1MiB window.
+    pub fn window_size(&self) -> u32 {
+        self.data_size_uncompressed.unwrap_or(LEGACY_CHUNK_WINDOW_SIZE)
+    }
+
     pub fn sha_hash(&self) -> Option<FSHAHash> {
         self.sha_hash.clone()
     }
@@ -110,22 +196,32 @@ impl FChunkHeader {
         self.stored_as() == (EChunkStorageFlags::Compressed)
     }
 
-    pub fn get_data(&self, reader:&mut ByteReader) -> Vec<u8> {
+    /// Reads the rest of the chunk file as its data, decompressing it if needed.
+    /// Fails on corrupt compressed data and on encrypted chunks, which can't be decrypted.
+    pub fn get_data(&self, reader:&mut ByteReader) -> ParseResult<Vec<u8>> {
         match self.stored_as {
             EChunkStorageFlags::Compressed => {
                 let compressed_data = reader.read_remaining();
                 let mut decoder = flate2::read::ZlibDecoder::new(compressed_data.as_slice());
-                let mut buffer:Vec<u8> = Vec::with_capacity(self.data_size_uncompressed().map(|x| x as usize).unwrap_or(0));
-                decoder.read_to_end(&mut buffer).unwrap();
+                // Not pre-sized from data_size_uncompressed, which comes from the untrusted chunk file
+                let mut buffer:Vec<u8> = Vec::new();
+                decoder.read_to_end(&mut buffer).map_err(|_| ParseError::DecompressionError)?;
 
-                buffer
+                Ok(buffer)
             },
             EChunkStorageFlags::None => {
-                reader.read_remaining()
+                Ok(reader.read_remaining())
             },
-            _ => {
-                panic!("Unsupported storage type: {:?}", self.stored_as);
+            EChunkStorageFlags::Encrypted => {
+                Err(ParseError::InvalidStorageFlag)
             }
         }
     }
+}
+
+impl ByteWritable for FChunkHeader {
+    /// Writes only the header, using the stored data_size_compressed
+    fn write(&self, writer: &mut ByteWriter) {
+        self.write_header(writer, self.data_size_compressed);
+    }
 }
\ No newline at end of file
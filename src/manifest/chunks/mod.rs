@@ -1 +1,504 @@
-pub mod chunk_header;
\ No newline at end of file
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    error::ParseError,
+    manifest::{
+        chunk_info::FChunkInfo,
+        rolling_hash,
+        shared::{EChunkHashFlags, EChunkStorageFlags, FGuid, FSHAHash},
+    },
+    reader::ByteReader,
+    writer::ByteWriter,
+    ParseResult,
+};
+
+use self::chunk_header::FChunkHeader;
+
+pub mod chunk_header;
+
+/// The path of a chunk file relative to the CDN base url, and its contents
+pub type ChunkFile = (String, Vec<u8>);
+
+/// A chunk file as stored on the CDN: a FChunkHeader followed by the chunk data.
+/// `data` is always kept decompressed.
+#[derive(Debug, Clone)]
+pub struct FChunk {
+    pub header: FChunkHeader,
+    pub data: Vec<u8>,
+}
+
+/// The result of `FChunk::verify`, a check is `None` when the header's hash type doesn't cover it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkVerification {
+    pub rolling_hash: Option<bool>,
+    pub sha1: Option<bool>,
+}
+
+impl ChunkVerification {
+    /// Returns whether every performed check passed, and at least one was performed
+    pub fn is_valid(&self) -> bool {
+        let checks = [self.rolling_hash, self.sha1];
+        checks.iter().any(Option::is_some) && checks.iter().flatten().all(|passed| *passed)
+    }
+}
+
+impl FChunk {
+    /// Parses a single chunk file
+    pub fn parse(bytes: &[u8]) -> ParseResult<FChunk> {
+        let mut reader = ByteReader::new(bytes.to_vec());
+        let header = FChunkHeader::parse(&mut reader)?;
+        let data = header.get_data(&mut reader)?;
+
+        Ok(FChunk { header, data })
+    }
+
+    /// Parses a single chunk file, failing if it isn't the chunk with `expected_guid`,
+    /// as happens when a stale CDN cache serves another chunk at the requested path.
+    /// The GUID is checked before the data is decompressed.
+    pub fn parse_verified(bytes: &[u8], expected_guid: &FGuid) -> ParseResult<FChunk> {
+        let mut reader = ByteReader::new(bytes.to_vec());
+        let header = FChunkHeader::parse(&mut reader)?;
+
+        if header.guid() != *expected_guid {
+            return Err(ParseError::GuidMismatch);
+        }
+
+        let data = header.get_data(&mut reader)?;
+
+        Ok(FChunk { header, data })
+    }
+
+    /// Creates a compressed chunk for `data`, taking its GUID and hashes from the manifest's chunk info
+    pub fn new(chunk_info: &FChunkInfo, data: Vec<u8>) -> FChunk {
+        let header = FChunkHeader::new_latest(
+            *chunk_info.guid(),
+            chunk_info.hash(),
+            EChunkStorageFlags::Compressed,
+            data.len() as u32,
+            chunk_info.sha_hash().clone(),
+        );
+
+        FChunk { header, data }
+    }
+
+    /// Serializes the chunk back into a chunk file, compressing the data if the header says so
+    pub fn serialize(&self) -> ParseResult<Vec<u8>> {
+        let stored_data = match self.header.stored_as() {
+            EChunkStorageFlags::Compressed => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(&self.data)
+                    .map_err(|_| ParseError::InvalidData)?;
+                encoder.finish().map_err(|_| ParseError::InvalidData)?
+            }
+            EChunkStorageFlags::None => self.data.clone(),
+            EChunkStorageFlags::Encrypted => return Err(ParseError::InvalidStorageFlag),
+        };
+
+        let mut writer = ByteWriter::new();
+        self.header.write_with_data(&mut writer, &stored_data);
+
+        Ok(writer.into_bytes())
+    }
+
+    /// Checks the data against the hashes the header's hash type marks as authoritative.
+    /// Headers older than `StoresShaAndHashType` only have a rolling hash.
+    pub fn verify(&self) -> ChunkVerification {
+        let hash_type = self
+            .header
+            .hash_type()
+            .unwrap_or(EChunkHashFlags::RollingPoly64);
+
+        let check_rolling_hash = matches!(
+            hash_type,
+            EChunkHashFlags::RollingPoly64 | EChunkHashFlags::Both
+        );
+        let check_sha1 = matches!(hash_type, EChunkHashFlags::Sha1 | EChunkHashFlags::Both);
+
+        ChunkVerification {
+            rolling_hash: check_rolling_hash.then(|| {
+                rolling_hash::get_hash_for_data_set(&self.data) == self.header.rolling_hash()
+            }),
+            sha1: check_sha1
+                .then(|| self.header.sha_hash() == Some(FSHAHash::new_from_hashable(&self.data))),
+        }
+    }
+
+    pub fn header(&self) -> &FChunkHeader {
+        &self.header
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// How a set of chunks is stored. The manifest's chunk list doesn't know this,
+/// only the chunk headers do, so it is computed from parsed headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageBreakdown {
+    pub compressed_count: usize,
+    /// Stored size of the compressed chunks
+    pub compressed_bytes: u64,
+    pub uncompressed_count: usize,
+    pub uncompressed_bytes: u64,
+    pub encrypted_count: usize,
+}
+
+impl StorageBreakdown {
+    pub fn new<'a>(headers: impl IntoIterator<Item = &'a FChunkHeader>) -> StorageBreakdown {
+        let mut breakdown = StorageBreakdown::default();
+
+        for header in headers {
+            let stored_size = header.data_size_compressed() as u64;
+            match header.stored_as() {
+                EChunkStorageFlags::Compressed => {
+                    breakdown.compressed_count += 1;
+                    breakdown.compressed_bytes += stored_size;
+                }
+                EChunkStorageFlags::None => {
+                    breakdown.uncompressed_count += 1;
+                    breakdown.uncompressed_bytes += stored_size;
+                }
+                EChunkStorageFlags::Encrypted => breakdown.encrypted_count += 1,
+            }
+        }
+
+        breakdown
+    }
+
+    /// Returns whether every chunk is stored the same way
+    pub fn is_consistent(&self) -> bool {
+        [
+            self.compressed_count,
+            self.uncompressed_count,
+            self.encrypted_count,
+        ]
+        .iter()
+        .filter(|count| **count > 0)
+        .count()
+            <= 1
+    }
+}
+
+/// Scans a blob of concatenated chunk files for the chunk with the given GUID and returns its decompressed data.
+/// Only the headers of the chunks before it are parsed, and scanning stops at the first match.
+pub fn find_chunk_in_blob(blob: &[u8], guid: &FGuid) -> ParseResult<Option<Vec<u8>>> {
+    let mut reader = ByteReader::new(blob.to_vec());
+
+    while reader.tell() < reader.length() {
+        let start = reader.tell();
+        let header = FChunkHeader::parse(&mut reader)?;
+        // Checked as usize is 32 bits on wasm32, where forged sizes could wrap around
+        let end = start
+            .checked_add(header.header_size() as usize)
+            .and_then(|end| end.checked_add(header.data_size_compressed() as usize))
+            .filter(|end| *end <= blob.len())
+            .ok_or(ParseError::Overflow)?;
+
+        if header.guid() == *guid {
+            return Ok(Some(FChunk::parse(&blob[start..end])?.data));
+        }
+
+        reader.seek(end);
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_header::LEGACY_CHUNK_WINDOW_SIZE, *};
+    use crate::manifest::{fixtures, shared::EChunkVersion};
+
+    /// Chunk file of the single chunk of a fixture manifest holding `data`
+    fn chunk_file(data: &[u8]) -> Vec<u8> {
+        let (manifest, chunk_data) = fixtures::manifest_with_data(&[("a.bin", data)]);
+        let chunk_info = &manifest.chunk_list.chunks()[0];
+
+        FChunk::new(chunk_info, chunk_data[chunk_info.guid()].clone())
+            .serialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn v4_chunk_header_is_fully_read() {
+        let chunk = FChunk::parse(&chunk_file(b"abc")).unwrap();
+
+        // magic, version, header size, compressed size, guid, rolling hash, storage,
+        // sha hash, hash type and uncompressed size
+        assert_eq!(
+            chunk.header().header_size(),
+            4 * 4 + 16 + 8 + 1 + 20 + 1 + 4
+        );
+        assert_eq!(chunk.header().window_size(), 3);
+        assert_eq!(chunk.data(), b"abc");
+    }
+
+    #[test]
+    fn legacy_chunk_headers_use_a_1mib_window() {
+        let header = FChunkHeader::new(
+            EChunkVersion::StoresShaAndHashType,
+            FGuid::default(),
+            0,
+            EChunkStorageFlags::None,
+            Some(EChunkHashFlags::RollingPoly64),
+            None,
+            None,
+        );
+        let mut writer = ByteWriter::new();
+        header.write_with_data(&mut writer, b"abc");
+
+        let chunk = FChunk::parse(writer.as_bytes()).unwrap();
+
+        assert_eq!(chunk.header().window_size(), LEGACY_CHUNK_WINDOW_SIZE);
+        assert_eq!(chunk.data(), b"abc");
+    }
+
+    #[test]
+    fn latest_headers_write_every_optional_field() {
+        let sha_hash = FSHAHash::new_from_hashable(b"abc");
+        let guid = FGuid {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+        };
+        let header =
+            FChunkHeader::new_latest(guid, 42, EChunkStorageFlags::None, 3, sha_hash.clone());
+        let mut writer = ByteWriter::new();
+        header.write_with_data(&mut writer, b"abc");
+
+        let parsed = FChunk::parse(writer.as_bytes()).unwrap();
+
+        let header = parsed.header();
+        assert_eq!(header.version(), EChunkVersion::Latest);
+        assert_eq!(header.guid(), guid);
+        assert_eq!(header.rolling_hash(), 42);
+        assert_eq!(header.sha_hash(), Some(sha_hash));
+        assert_eq!(header.hash_type(), Some(EChunkHashFlags::Both));
+        assert_eq!(header.data_size_uncompressed(), Some(3));
+        assert_eq!(parsed.data(), b"abc");
+    }
+
+    #[test]
+    fn parsed_headers_are_written_back_unchanged() {
+        let bytes = chunk_file(b"abc");
+        let header = FChunk::parse(&bytes).unwrap().header().clone();
+
+        let mut writer = ByteWriter::new();
+        writer.write(&header);
+        let written = writer.into_bytes();
+        assert_eq!(written, bytes[..header.header_size() as usize]);
+
+        let reparsed = FChunkHeader::parse(&mut ByteReader::new(written)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reparsed).unwrap(),
+            serde_json::to_value(&header).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_verified_rejects_another_chunk() {
+        let bytes = chunk_file(b"abc");
+        let guid = FChunk::parse(&bytes).unwrap().header().guid();
+        let other = FGuid {
+            a: guid.a ^ 1,
+            ..guid
+        };
+
+        assert_eq!(
+            FChunk::parse_verified(&bytes, &guid).unwrap().data(),
+            b"abc"
+        );
+        assert!(matches!(
+            FChunk::parse_verified(&bytes, &other),
+            Err(ParseError::GuidMismatch)
+        ));
+    }
+
+    /// Offset of the storage flags byte: magic, version, header size, compressed size, guid and rolling hash
+    const STORED_AS_OFFSET: usize = 4 * 4 + 16 + 8;
+
+    #[test]
+    fn corrupt_compressed_data_is_an_error() {
+        let mut bytes = chunk_file(b"abc");
+        let header_size = FChunk::parse(&bytes).unwrap().header().header_size() as usize;
+        bytes.truncate(header_size);
+        bytes.extend_from_slice(b"not zlib data");
+
+        assert!(matches!(
+            FChunk::parse(&bytes),
+            Err(ParseError::DecompressionError)
+        ));
+    }
+
+    #[test]
+    fn encrypted_and_unknown_storage_flags_are_errors() {
+        let mut bytes = chunk_file(b"abc");
+
+        bytes[STORED_AS_OFFSET] = 2;
+        assert!(matches!(
+            FChunk::parse(&bytes),
+            Err(ParseError::InvalidStorageFlag)
+        ));
+
+        bytes[STORED_AS_OFFSET] = 0xff;
+        assert!(matches!(
+            FChunk::parse(&bytes),
+            Err(ParseError::InvalidStorageFlag)
+        ));
+    }
+
+    #[test]
+    fn find_chunk_in_blob_finds_the_second_chunk() {
+        let (manifest, chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefgh")]);
+        let mut blob = Vec::new();
+        for chunk_info in manifest.chunk_list.chunks() {
+            let chunk = FChunk::new(chunk_info, chunk_data[chunk_info.guid()].clone());
+            blob.extend(chunk.serialize().unwrap());
+        }
+        let guid = manifest.chunk_list.chunks()[1].guid();
+
+        assert_eq!(find_chunk_in_blob(&blob, guid).unwrap().unwrap(), b"efgh");
+        assert_eq!(find_chunk_in_blob(&blob, &FGuid::default()).unwrap(), None);
+
+        blob.pop();
+        assert!(matches!(
+            find_chunk_in_blob(&blob, guid),
+            Err(ParseError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn find_chunk_in_blob_fails_on_a_corrupt_match() {
+        let guid = FGuid {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+        };
+        let header = FChunkHeader::new_latest(
+            guid,
+            0,
+            EChunkStorageFlags::Compressed,
+            4,
+            FSHAHash::default(),
+        );
+        let mut writer = ByteWriter::new();
+        header.write_with_data(&mut writer, b"not zlib data");
+        let blob = writer.into_bytes();
+
+        assert!(matches!(
+            find_chunk_in_blob(&blob, &guid),
+            Err(ParseError::DecompressionError)
+        ));
+    }
+
+    #[test]
+    fn find_chunk_in_blob_fails_on_a_forged_compressed_size() {
+        let mut blob = chunk_file(b"abc");
+        // The compressed size follows the magic, version and header size
+        blob[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            find_chunk_in_blob(&blob, &FGuid::default()),
+            Err(ParseError::Overflow)
+        ));
+    }
+
+    /// A chunk holding "abcd" whose header has the given hash type,
+    /// and a correct rolling hash and SHA1 hash unless told otherwise
+    fn hashed_chunk(hash_type: Option<EChunkHashFlags>, rolling_ok: bool, sha1_ok: bool) -> FChunk {
+        let data = b"abcd".to_vec();
+        let rolling_hash = rolling_hash::get_hash_for_data_set(&data) + u64::from(!rolling_ok);
+        let sha_hash = if sha1_ok {
+            FSHAHash::new_from_hashable(&data)
+        } else {
+            FSHAHash::default()
+        };
+        let header = FChunkHeader::new(
+            EChunkVersion::Latest,
+            FGuid::default(),
+            rolling_hash,
+            EChunkStorageFlags::None,
+            hash_type,
+            Some(data.len() as u32),
+            Some(sha_hash),
+        );
+
+        FChunk { header, data }
+    }
+
+    #[test]
+    fn verify_only_checks_the_hashes_of_the_hash_type() {
+        use EChunkHashFlags::{Both, RollingPoly64, Sha1};
+
+        let verify = |hash_type, rolling_ok, sha1_ok| {
+            let chunk = hashed_chunk(Some(hash_type), rolling_ok, sha1_ok);
+            let verification = chunk.verify();
+            (verification.rolling_hash, verification.sha1)
+        };
+
+        assert_eq!(verify(RollingPoly64, true, false), (Some(true), None));
+        assert_eq!(verify(RollingPoly64, false, true), (Some(false), None));
+        assert_eq!(verify(Sha1, false, true), (None, Some(true)));
+        assert_eq!(verify(Sha1, true, false), (None, Some(false)));
+        assert_eq!(verify(Both, true, true), (Some(true), Some(true)));
+        assert_eq!(verify(Both, true, false), (Some(true), Some(false)));
+        assert_eq!(verify(EChunkHashFlags::None, false, false), (None, None));
+    }
+
+    #[test]
+    fn verify_is_valid_when_every_performed_check_passed() {
+        use EChunkHashFlags::Both;
+
+        assert!(hashed_chunk(Some(Both), true, true).verify().is_valid());
+        assert!(!hashed_chunk(Some(Both), false, true).verify().is_valid());
+        assert!(!hashed_chunk(Some(EChunkHashFlags::None), true, true)
+            .verify()
+            .is_valid());
+        // Headers without a hash type only have a rolling hash
+        assert!(hashed_chunk(None, true, false).verify().is_valid());
+    }
+
+    #[test]
+    fn storage_breakdown_counts_each_storage_type() {
+        let mut headers = Vec::new();
+        for (stored_as, data) in [
+            (EChunkStorageFlags::Compressed, &b"aaaaaaaa"[..]),
+            (EChunkStorageFlags::None, b"abc"),
+            (EChunkStorageFlags::Compressed, b"bbbb"),
+        ] {
+            let header = FChunkHeader::new_latest(
+                FGuid::default(),
+                0,
+                stored_as,
+                data.len() as u32,
+                FSHAHash::default(),
+            );
+            let chunk = FChunk {
+                header,
+                data: data.to_vec(),
+            };
+            headers.push(FChunk::parse(&chunk.serialize().unwrap()).unwrap().header);
+        }
+
+        let breakdown = StorageBreakdown::new(&headers);
+
+        assert_eq!(breakdown.compressed_count, 2);
+        assert_eq!(
+            breakdown.compressed_bytes,
+            (headers[0].data_size_compressed() + headers[2].data_size_compressed()) as u64
+        );
+        assert_eq!(breakdown.uncompressed_count, 1);
+        assert_eq!(breakdown.uncompressed_bytes, 3);
+        assert_eq!(breakdown.encrypted_count, 0);
+        assert!(!breakdown.is_consistent());
+        assert!(StorageBreakdown::new(&headers[..1]).is_consistent());
+    }
+}
@@ -1,22 +1,45 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::{error::ParseError, reader::ByteReader, ParseResult};
+use crate::{
+    error::{ParseError, ParseWarning},
+    reader::ByteReader,
+    ParseResult,
+};
+
+use super::ParseOptions;
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FCustomFields {
-    _size: u32,
-    _version: u8,
+    pub(crate) _size: u32,
+    pub(crate) _version: u8,
+    /// Serialized to JSON sorted by key
+    #[serde(serialize_with = "serialize_sorted")]
     pub fields: HashMap<String, String>,
 }
 
+fn serialize_sorted<S: serde::Serializer>(
+    fields: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(fields.iter().collect::<BTreeMap<_, _>>())
+}
+
 impl FCustomFields {
     /// This function is used to parse Custom Fields from a ByteReader
-    pub fn parse(reader: &mut ByteReader) -> ParseResult<FCustomFields> {
+    pub fn parse(
+        reader: &mut ByteReader,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> ParseResult<FCustomFields> {
         let start = reader.tell();
 
         let size = reader.read()?;
         let version = reader.read()?;
-        let count = reader.read()?;
+        let count: u32 = reader.read()?;
+
+        // Every field holds at least the two string lengths
+        reader.check_count(count as usize, 8)?;
 
         let mut fields = HashMap::new();
         fields.reserve(count as usize);
@@ -34,7 +57,15 @@ impl FCustomFields {
                 size,
                 reader.tell() - start
             );
-            return Err(ParseError::SizeMismatch);
+            if !options.lenient_sizes {
+                return Err(ParseError::SizeMismatch);
+            }
+            warnings.push(ParseWarning::SectionSizeMismatch {
+                section: "CustomFields",
+                expected: size,
+                actual: reader.tell() - start,
+            });
+            reader.seek(start + size as usize);
         }
 
         Ok(FCustomFields {
@@ -48,26 +79,119 @@ impl FCustomFields {
     pub fn write(&self, writer: &mut crate::writer::ByteWriter) {
         use crate::writer::ByteWritable;
 
-        // Calculate the size first by writing to a temporary buffer
-        let mut temp_writer = crate::writer::ByteWriter::new();
-        temp_writer.write(&self._version);
-        temp_writer.write(&(self.fields.len() as u32));
-
-        for (key, value) in &self.fields {
-            temp_writer.write(key);
-            temp_writer.write(value);
-        }
-
-        let size = (temp_writer.tell() + 4) as u32; // +4 for the size field itself
-
-        // Write the actual data with correct size
-        writer.write(&size);
+        // Reserve the size field and back-patch it once the section is written
+        let start = writer.tell();
+        writer.write(&0u32);
         writer.write(&self._version);
         writer.write(&(self.fields.len() as u32));
 
-        for (key, value) in &self.fields {
+        // Sorted so the written bytes don't depend on HashMap iteration order
+        for (key, value) in self.sorted_fields() {
             writer.write(key);
             writer.write(value);
         }
+
+        writer.write_u32_at(start, (writer.tell() - start) as u32); // the size includes the size field itself
+    }
+
+    /// Returns the fields sorted by key, as `fields` iterates in an unspecified order
+    pub fn sorted_fields(&self) -> Vec<(&String, &String)> {
+        let mut fields = self.fields.iter().collect::<Vec<_>>();
+        fields.sort();
+        fields
+    }
+
+    /// Returns how many bytes `write` produces, without writing anything
+    pub fn write_size(&self) -> usize {
+        use crate::writer::string_size;
+
+        // size, version and count
+        4 + 1
+            + 4
+            + self
+                .fields
+                .iter()
+                .map(|(key, value)| string_size(key) + string_size(value))
+                .sum::<usize>()
+    }
+
+    /// Returns the version of the custom fields section, unrelated to the feature level
+    pub fn version(&self) -> u8 {
+        self._version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::ByteWriter;
+
+    #[test]
+    fn huge_field_counts_fail_cleanly() {
+        let mut writer = ByteWriter::new();
+        writer.write(&13u32);
+        writer.write(&0u8);
+        writer.write(&u32::MAX);
+        writer.write(&0u32);
+
+        let mut reader = ByteReader::new(writer.into_bytes());
+        let result = FCustomFields::parse(&mut reader, &ParseOptions::default(), &mut vec![]);
+
+        assert!(matches!(result, Err(ParseError::Overflow)));
+    }
+
+    #[test]
+    fn back_patched_size_matches_writing_the_body_twice() {
+        let fields = FCustomFields {
+            fields: HashMap::from([
+                ("b".to_owned(), "22".to_owned()),
+                ("a".to_owned(), "1".to_owned()),
+            ]),
+            ..Default::default()
+        };
+
+        // The previous approach: write the body to a temporary buffer to learn the section size
+        let mut body = ByteWriter::new();
+        body.write(&fields._version);
+        body.write(&(fields.fields.len() as u32));
+        for (key, value) in fields.sorted_fields() {
+            body.write(key);
+            body.write(value);
+        }
+        let mut expected = ByteWriter::new();
+        expected.write(&(body.tell() as u32 + 4));
+        expected.write_bytes(body.as_bytes());
+
+        let mut writer = ByteWriter::new();
+        fields.write(&mut writer);
+
+        assert_eq!(writer.as_bytes(), expected.as_bytes());
+        assert_eq!(fields.write_size(), writer.tell());
+    }
+
+    #[test]
+    fn fields_are_sorted_by_key() {
+        let fields = FCustomFields {
+            fields: HashMap::from([
+                ("c".to_owned(), "3".to_owned()),
+                ("a".to_owned(), "1".to_owned()),
+                ("B".to_owned(), "2".to_owned()),
+            ]),
+            ..Default::default()
+        };
+
+        let keys = fields
+            .sorted_fields()
+            .into_iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["B", "a", "c"]);
+
+        let mut writer = ByteWriter::new();
+        fields.write(&mut writer);
+        let mut reader = ByteReader::new(writer.into_bytes());
+        let parsed =
+            FCustomFields::parse(&mut reader, &ParseOptions::default(), &mut vec![]).unwrap();
+        assert_eq!(parsed.sorted_fields(), fields.sorted_fields());
     }
 }
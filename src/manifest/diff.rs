@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+use super::file_manifest::FFileManifest;
+
+/// How the chunks making up a single file changed between two builds
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChunkDiff {
+    pub filename: String,
+    /// Chunks the new version of the file references but the old one didn't
+    pub added: Vec<String>,
+    /// Chunks the old version of the file referenced but the new one doesn't
+    pub removed: Vec<String>,
+    /// Chunks referenced by both versions
+    pub kept: Vec<String>,
+}
+
+impl FileChunkDiff {
+    /// A file missing from one of the manifests is treated as referencing no chunks
+    pub fn new(
+        filename: &str,
+        old: Option<&FFileManifest>,
+        new: Option<&FFileManifest>,
+    ) -> FileChunkDiff {
+        let old_guids = chunk_guids(old);
+        let new_guids = chunk_guids(new);
+
+        let mut diff = FileChunkDiff {
+            filename: filename.to_owned(),
+            added: vec![],
+            removed: vec![],
+            kept: vec![],
+        };
+
+        for guid in &new_guids {
+            if old_guids.contains(guid) {
+                diff.kept.push(guid.clone());
+            } else {
+                diff.added.push(guid.clone());
+            }
+        }
+
+        for guid in &old_guids {
+            if !new_guids.contains(guid) {
+                diff.removed.push(guid.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+/// Returns the distinct chunk GUIDs of a file, in the order the file references them
+fn chunk_guids(file: Option<&FFileManifest>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    file.map(|file| file.chunk_parts())
+        .unwrap_or_default()
+        .iter()
+        .map(|part| part.guid().to_hex_lower())
+        .filter(|guid| seen.insert(guid.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manifest::{fixtures, FManifest};
+
+    #[test]
+    fn file_chunk_diff_of_a_file_with_one_changed_chunk() {
+        let old = fixtures::manifest(&[("a.bin", b"aaaabbbbcccc")]);
+        let new = fixtures::manifest(&[("a.bin", b"aaaaXXXXcccc")]);
+
+        let diff = FManifest::file_chunk_diff(&old, &new, "a.bin");
+
+        let guid = |manifest: &FManifest, index: usize| {
+            manifest.chunk_list.chunks()[index].guid().to_hex_lower()
+        };
+        assert_eq!(diff.added, [guid(&new, 1)]);
+        assert_eq!(diff.removed, [guid(&old, 1)]);
+        assert_eq!(diff.kept, [guid(&old, 0), guid(&old, 2)]);
+    }
+}
@@ -1,9 +1,16 @@
+use std::collections::{HashMap, HashSet};
+
+use sha1::{Digest, Sha1};
+
+use crate::{error::ParseError, ParseResult};
+
 use super::{
     chunk_part::FChunkPart,
-    shared::{FSHAHash, UnknownHash, MD5_DIGEST_SIZE, SHA256_DIGEST_SIZE},
+    shared::{FGuid, FSHAHash, UnknownHash, MD5_DIGEST_SIZE, SHA256_DIGEST_SIZE},
 };
 
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FFileManifest {
     pub(crate) filename: String,
     pub(crate) syslink_target: String,
@@ -14,7 +21,7 @@ pub struct FFileManifest {
     pub(crate) mime_type: Option<String>,
     pub(crate) hash_md5: Option<UnknownHash<MD5_DIGEST_SIZE>>,
     pub(crate) hash_sha256: Option<UnknownHash<SHA256_DIGEST_SIZE>>,
-    pub(crate) file_size: u32,
+    pub(crate) file_size: u64,
 }
 
 impl PartialEq for FFileManifest {
@@ -48,18 +55,41 @@ impl FFileManifest {
         self.hash_sha256.as_ref()
     }
 
-    pub fn file_size(&self) -> u32 {
+    pub fn file_size(&self) -> u64 {
         self.file_size
     }
 
+    /// Returns the file size the chunk parts add up to. Parsing sets `file_size` to this,
+    /// but a manifest built from JSON or edited afterwards can claim a different size.
+    /// Summed in u64, files can be larger than 4GB.
+    pub fn recompute_file_size(&self) -> u64 {
+        self.chunk_parts.iter().map(|part| part.size() as u64).sum()
+    }
+
+    /// Returns whether the chunk parts cover exactly the stored file size
+    pub fn has_consistent_file_size(&self) -> bool {
+        self.recompute_file_size() == self.file_size
+    }
+
     pub fn filename(&self) -> &str {
         &self.filename
     }
 
+    /// Returns the filename with forward slashes only, as Epic manifests mix `\` and `/`
+    pub fn normalized_filename(&self) -> String {
+        self.filename.replace('\\', "/")
+    }
+
     pub fn syslink_target(&self) -> &str {
         &self.syslink_target
     }
 
+    /// Returns whether the file is a symlink to `syslink_target`, which has no chunk data:
+    /// extractors should create the link instead of assembling the file
+    pub fn is_symlink(&self) -> bool {
+        !self.syslink_target.is_empty()
+    }
+
     pub fn mime_type(&self) -> Option<&str> {
         self.mime_type.as_deref()
     }
@@ -79,4 +109,119 @@ impl FFileManifest {
     pub fn raw_flags(&self) -> u8 {
         self.flags
     }
+
+    /// Returns the number of parts per unique chunk referenced, above 1 when some chunks are used more than once.
+    /// Files without parts return 0.
+    pub fn chunk_reuse_ratio(&self) -> f64 {
+        let unique_chunks = self
+            .chunk_parts
+            .iter()
+            .map(|part| part.guid())
+            .collect::<HashSet<_>>();
+
+        if unique_chunks.is_empty() {
+            0.0
+        } else {
+            self.chunk_parts.len() as f64 / unique_chunks.len() as f64
+        }
+    }
+
+    /// Rebuilds the file from decompressed chunk data (keyed by chunk GUID).
+    ///
+    /// Every part is fed into a SHA1 hasher as it gets copied, so the returned bool
+    /// telling whether the file matches its stored hash doesn't need a second pass.
+    pub fn reconstruct_and_verify(
+        &self,
+        chunk_data: &HashMap<FGuid, Vec<u8>>,
+    ) -> ParseResult<(Vec<u8>, bool)> {
+        let mut hasher = Sha1::new();
+        let mut data = Vec::new();
+
+        for part in &self.chunk_parts {
+            let chunk = chunk_data
+                .get(part.guid())
+                .ok_or(ParseError::MissingChunk(*part.guid()))?;
+
+            let bytes = chunk.get(part.chunk_range()?).ok_or(ParseError::Overflow)?;
+
+            hasher.update(bytes);
+            data.extend_from_slice(bytes);
+        }
+
+        let hash = FSHAHash::new(hasher.finalize().into());
+
+        Ok((data, hash == self.hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::fixtures;
+
+    #[test]
+    fn reconstruct_and_verify_hashes_while_assembling() {
+        let (manifest, chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefghij")]);
+        let file = &manifest.file_list.entries()[0];
+
+        let (data, hash_matches) = file.reconstruct_and_verify(&chunk_data).unwrap();
+
+        assert_eq!(data, b"abcdefghij");
+        assert!(hash_matches);
+        assert_eq!(FSHAHash::new_from_hashable(&data), *file.hash());
+    }
+
+    #[test]
+    fn reconstruct_and_verify_reports_a_wrong_hash() {
+        let (mut manifest, chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefgh")]);
+        manifest.file_list.entries[0].hash = FSHAHash::default();
+
+        let (data, hash_matches) = manifest.file_list.entries()[0]
+            .reconstruct_and_verify(&chunk_data)
+            .unwrap();
+
+        assert_eq!(data, b"abcdefgh");
+        assert!(!hash_matches);
+    }
+
+    #[test]
+    fn reconstruct_and_verify_fails_on_a_missing_chunk() {
+        let (manifest, mut chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefgh")]);
+        let file = &manifest.file_list.entries()[0];
+        let missing = *file.chunk_parts()[1].guid();
+        chunk_data.remove(&missing);
+
+        assert!(matches!(
+            file.reconstruct_and_verify(&chunk_data),
+            Err(ParseError::MissingChunk(guid)) if guid == missing
+        ));
+    }
+
+    #[test]
+    fn reconstruct_and_verify_ignores_a_forged_file_size() {
+        let (mut manifest, chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefgh")]);
+        manifest.file_list.entries[0].file_size = u64::MAX;
+
+        let (data, hash_matches) = manifest.file_list.entries()[0]
+            .reconstruct_and_verify(&chunk_data)
+            .unwrap();
+
+        assert_eq!(data, b"abcdefgh");
+        assert!(hash_matches);
+    }
+
+    #[test]
+    fn chunk_reuse_ratio_counts_repeated_chunks() {
+        let manifest = fixtures::manifest(&[("repeated.bin", b"abcdabcdefgh"), ("empty.bin", b"")]);
+
+        // The first two parts share the "abcd" chunk
+        let file = manifest.find_file("repeated.bin").unwrap();
+        assert_eq!(file.chunk_parts().len(), 3);
+        assert_eq!(file.chunk_reuse_ratio(), 1.5);
+
+        assert_eq!(
+            manifest.find_file("empty.bin").unwrap().chunk_reuse_ratio(),
+            0.0
+        );
+    }
 }
@@ -1,7 +1,20 @@
-use super::{chunk_part::FChunkPart, file_manifest::FFileManifest, shared::UnknownHash};
-use crate::{error::ParseError, reader::ByteReader, ParseResult};
+use super::{
+    chunk_part::FChunkPart,
+    file_manifest::FFileManifest,
+    shared::{UnknownHash, SHA256_DIGEST_SIZE},
+    ParseOptions,
+};
+use crate::{
+    error::{ParseError, ParseWarning},
+    reader::ByteReader,
+    ParseResult,
+};
+
+/// The latest file list version the parser knows the layout of
+pub const LATEST_FILE_LIST_VERSION: u8 = 2;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FFileManifestList {
     pub(crate) _version: u8,
     pub(crate) _size: u32,
@@ -11,17 +24,42 @@ pub struct FFileManifestList {
 
 impl FFileManifestList {
     /// This function is used to parse a FFileManifestList from a ByteReader
-    pub fn parse(reader: &mut ByteReader) -> ParseResult<FFileManifestList> {
+    ///
+    /// Version 0 only stores the filename, symlink target, hash, flags, install tags and chunk parts columns.
+    /// Version 1 adds the MD5 and MIME type columns, and version 2 the SHA256 column.
+    ///
+    /// Every known version stores the install tags of each file as its own string array,
+    /// there is no shared tag table referenced by index. Newer versions are read with the
+    /// version 2 layout and reported with a `ParseWarning::UnknownSectionVersion`.
+    pub fn parse(
+        reader: &mut ByteReader,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> ParseResult<FFileManifestList> {
         let reader_start = reader.tell();
 
         let size = reader.read()?;
         let version = reader.read()?;
-        let count = reader.read()?;
+        let count: u32 = reader.read()?;
+
+        if version > LATEST_FILE_LIST_VERSION {
+            warnings.push(ParseWarning::UnknownSectionVersion {
+                section: "FileManifestList",
+                version,
+            });
+        }
+
+        // Two string lengths, the hash, flags, the tag and part counts
+        reader.check_count(count as usize, 4 + 4 + 20 + 1 + 4 + 4)?;
 
         let mut entries: Vec<FFileManifest> = vec![Default::default(); count as usize];
 
         for entry in entries.iter_mut() {
             entry.filename = reader.read()?;
+
+            if options.sanitize_filenames {
+                check_filename(&entry.filename)?;
+            }
         }
 
         for entry in entries.iter_mut() {
@@ -42,45 +80,56 @@ impl FFileManifestList {
 
         for entry in entries.iter_mut() {
             let part_count = reader.read::<u32>()?;
-            let mut file_offset = 0;
+            // Accumulate in u64 so files larger than 4GB don't wrap on wasm32, where usize is 32 bits
+            let mut file_offset = 0u64;
 
-            //make sure we have enough capacity to push every parts without reallocating
-            entry
-                .chunk_parts
-                .reserve(part_count as usize - entry.chunk_parts.capacity());
-            for _ in 0..part_count {
-                let part = FChunkPart::parse(reader, file_offset)?;
-                file_offset += part.size() as usize;
-                entry.chunk_parts.push(part);
-            }
+            // Each part holds its struct size, guid, offset and size
+            reader.check_count(part_count as usize, 4 + 16 + 4 + 4)?;
+
+            entry.chunk_parts = reader.read_array_with_count(part_count as usize, |reader| {
+                let part = FChunkPart::parse(reader, file_offset, options, warnings)?;
+                file_offset += part.size() as u64;
+                Ok(part)
+            })?;
         }
 
+        // Every entry has these columns, even when empty, so a failed read means the section is desynced
         if version >= 1 {
             for entry in entries.iter_mut() {
                 let has_md5 = reader.read::<u32>()?;
                 if has_md5 != 0 {
-                    entry.hash_md5 = UnknownHash::from_byte_reader(reader).ok();
+                    entry.hash_md5 = Some(UnknownHash::from_byte_reader(reader)?);
                 }
             }
 
+            // Files without a MIME type store an empty string
             for entry in entries.iter_mut() {
-                entry.mime_type = reader.read().ok();
+                let mime_type: String = reader.read()?;
+                entry.mime_type = (!mime_type.is_empty()).then_some(mime_type);
             }
         }
 
         if version >= 2 {
             for entry in entries.iter_mut() {
-                entry.hash_sha256 = UnknownHash::from_byte_reader(reader).ok();
+                entry.hash_sha256 = Some(UnknownHash::from_byte_reader(reader)?);
             }
         }
 
         for entry in entries.iter_mut() {
-            entry.file_size = entry.chunk_parts.iter().map(|part| part.size()).sum();
+            entry.file_size = entry.recompute_file_size();
         }
 
         if reader_start + size as usize != reader.tell() {
             println!("FileManifestList size mismatch: expected {} but got {}\nFileManifestList version : {}", size, reader.tell() - reader_start, version);
-            return Err(ParseError::InvalidData);
+            if !options.lenient_sizes {
+                return Err(ParseError::InvalidData);
+            }
+            warnings.push(ParseWarning::SectionSizeMismatch {
+                section: "FileManifestList",
+                expected: size,
+                actual: reader.tell() - reader_start,
+            });
+            reader.seek(reader_start + size as usize);
         }
 
         Ok(FFileManifestList {
@@ -95,41 +144,42 @@ impl FFileManifestList {
     pub fn write(&self, writer: &mut crate::writer::ByteWriter) {
         use crate::writer::ByteWritable;
 
-        // Calculate the size first by writing to a temporary buffer
-        let mut temp_writer = crate::writer::ByteWriter::new();
-        temp_writer.write(&self._version);
-        temp_writer.write(&(self.entries.len() as u32));
+        // Reserve the size field and back-patch it once the section is written
+        let start = writer.tell();
+        writer.write(&0u32);
+        writer.write(&self._version);
+        writer.write(&(self.entries.len() as u32));
 
         // Write filenames
         for entry in &self.entries {
-            temp_writer.write(&entry.filename);
+            writer.write(&entry.filename);
         }
 
         // Write symlink targets
         for entry in &self.entries {
-            temp_writer.write(&entry.syslink_target);
+            writer.write(&entry.syslink_target);
         }
 
         // Write hashes
         for entry in &self.entries {
-            temp_writer.write(&entry.hash);
+            writer.write(&entry.hash);
         }
 
         // Write flags
         for entry in &self.entries {
-            temp_writer.write(&entry.flags);
+            writer.write(&entry.flags);
         }
 
         // Write install tags arrays
         for entry in &self.entries {
-            temp_writer.write_array(&entry.install_tags);
+            writer.write_array(&entry.install_tags);
         }
 
         // Write chunk parts
         for entry in &self.entries {
-            temp_writer.write(&(entry.chunk_parts.len() as u32));
+            writer.write(&(entry.chunk_parts.len() as u32));
             for part in &entry.chunk_parts {
-                part.write(&mut temp_writer);
+                part.write(writer);
             }
         }
 
@@ -138,105 +188,357 @@ impl FFileManifestList {
             // Write MD5 hashes
             for entry in &self.entries {
                 if let Some(ref md5_hash) = entry.hash_md5 {
-                    temp_writer.write(&1u32); // has_md5 = true
-                    temp_writer.write(md5_hash);
+                    writer.write(&1u32); // has_md5 = true
+                    writer.write(md5_hash);
                 } else {
-                    temp_writer.write(&0u32); // has_md5 = false
+                    writer.write(&0u32); // has_md5 = false
                 }
             }
 
             // Write MIME types
             for entry in &self.entries {
                 if let Some(ref mime_type) = entry.mime_type {
-                    temp_writer.write(mime_type);
+                    writer.write(mime_type);
                 } else {
-                    temp_writer.write(&String::new());
+                    writer.write(&String::new());
                 }
             }
         }
 
         if self._version >= 2 {
-            // Write SHA256 hashes
+            // Write SHA256 hashes, the reader expects one per entry so missing ones are zeroed
             for entry in &self.entries {
-                if let Some(ref sha256_hash) = entry.hash_sha256 {
-                    temp_writer.write(sha256_hash);
+                match entry.hash_sha256 {
+                    Some(ref sha256_hash) => writer.write(sha256_hash),
+                    None => writer.write_bytes(&[0u8; SHA256_DIGEST_SIZE]),
                 }
             }
         }
 
-        let size = (temp_writer.tell() + 4) as u32; // +4 for the size field itself
+        writer.write_u32_at(start, (writer.tell() - start) as u32); // the size includes the size field itself
+    }
 
-        // Write the actual data with correct size
-        writer.write(&size);
-        writer.write(&self._version);
-        writer.write(&(self.entries.len() as u32));
+    /// Returns how many bytes `write` produces, without writing anything
+    pub fn write_size(&self) -> usize {
+        use crate::writer::string_size;
 
-        // Write filenames
-        for entry in &self.entries {
-            writer.write(&entry.filename);
-        }
+        // size, version and count
+        let mut size = 4 + 1 + 4;
 
-        // Write symlink targets
         for entry in &self.entries {
-            writer.write(&entry.syslink_target);
-        }
+            size += string_size(&entry.filename);
+            size += string_size(&entry.syslink_target);
+            size += 20 + 1; // hash and flags
+            size += 4 + entry
+                .install_tags
+                .iter()
+                .map(|tag| string_size(tag))
+                .sum::<usize>();
+            size += 4 + entry
+                .chunk_parts
+                .iter()
+                .map(|part| part.write_size())
+                .sum::<usize>();
 
-        // Write hashes
-        for entry in &self.entries {
-            writer.write(&entry.hash);
-        }
+            if self._version >= 1 {
+                size += 4 + if entry.hash_md5.is_some() { 16 } else { 0 };
+                size += string_size(entry.mime_type.as_deref().unwrap_or_default());
+            }
 
-        // Write flags
-        for entry in &self.entries {
-            writer.write(&entry.flags);
+            if self._version >= 2 {
+                size += SHA256_DIGEST_SIZE;
+            }
         }
 
-        // Write install tags arrays
-        for entry in &self.entries {
-            writer.write_array(&entry.install_tags);
-        }
+        size
+    }
 
-        // Write chunk parts
-        for entry in &self.entries {
-            writer.write(&(entry.chunk_parts.len() as u32));
-            for part in &entry.chunk_parts {
-                part.write(writer);
-            }
+    /// Drops the MD5, MIME type and SHA256 columns and downgrades the list to version 0.
+    /// The SHA1 hash is kept since it is the primary integrity field.
+    pub fn strip_extra_hashes(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.hash_md5 = None;
+            entry.hash_sha256 = None;
+            entry.mime_type = None;
         }
 
-        // Handle version-specific fields
-        if self._version >= 1 {
-            // Write MD5 hashes
-            for entry in &self.entries {
-                if let Some(ref md5_hash) = entry.hash_md5 {
-                    writer.write(&1u32); // has_md5 = true
-                    writer.write(md5_hash);
-                } else {
-                    writer.write(&0u32); // has_md5 = false
-                }
-            }
+        self._version = 0;
+    }
 
-            // Write MIME types
-            for entry in &self.entries {
-                if let Some(ref mime_type) = entry.mime_type {
-                    writer.write(mime_type);
-                } else {
-                    writer.write(&String::new());
-                }
+    /// Returns a list where files from `overlay` replace the files with the same name,
+    /// and files only present in `overlay` are appended.
+    pub fn overlay(&self, overlay: &FFileManifestList) -> FFileManifestList {
+        let mut entries = self.entries.clone();
+
+        for file in &overlay.entries {
+            match entries
+                .iter_mut()
+                .find(|entry| entry.filename == file.filename)
+            {
+                Some(entry) => *entry = file.clone(),
+                None => entries.push(file.clone()),
             }
         }
 
-        if self._version >= 2 {
-            // Write SHA256 hashes
-            for entry in &self.entries {
-                if let Some(ref sha256_hash) = entry.hash_sha256 {
-                    writer.write(sha256_hash);
-                }
-            }
+        FFileManifestList {
+            _version: self._version.max(overlay._version),
+            _size: 0,
+            _count: entries.len() as u32,
+            entries,
+        }
+    }
+
+    /// Sorts the files by name, giving a file order that doesn't depend on how the list was edited
+    pub fn sort_by_filename(&mut self) {
+        self.entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+    }
+
+    /// Rewrites every filename to use forward slashes only
+    pub fn normalize_paths(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.filename = entry.normalized_filename();
         }
     }
 
+    /// Returns the version of the file list section, which decides the columns it stores
+    pub fn version(&self) -> u8 {
+        self._version
+    }
+
     pub fn entries(&self) -> &Vec<FFileManifest> {
         &self.entries
     }
 }
+
+/// Fails on filenames an extractor shouldn't trust, see `ParseOptions::sanitize_filenames`.
+/// Invalid UTF-8 and UTF-16 are read as U+FFFD, so it is rejected too.
+fn check_filename(filename: &str) -> ParseResult<()> {
+    let is_unsafe = filename
+        .chars()
+        .any(|c| c.is_control() || c == char::REPLACEMENT_CHARACTER);
+
+    if is_unsafe {
+        return Err(ParseError::InvalidData);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::shared::FGuid, writer::ByteWriter};
+
+    fn list(entries: Vec<FFileManifest>) -> FFileManifestList {
+        FFileManifestList {
+            _version: 0,
+            _size: 0,
+            _count: entries.len() as u32,
+            entries,
+        }
+    }
+
+    fn reparse(list: &FFileManifestList) -> ParseResult<FFileManifestList> {
+        let mut writer = ByteWriter::new();
+        list.write(&mut writer);
+
+        let mut reader = ByteReader::new(writer.into_bytes());
+        FFileManifestList::parse(&mut reader, &ParseOptions::default(), &mut vec![])
+    }
+
+    #[test]
+    fn file_offsets_accumulate_past_u32_max() {
+        let part_size = 0x8000_0000;
+        let guid = FGuid {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+        };
+        let file = FFileManifest {
+            filename: "huge.bin".to_owned(),
+            chunk_parts: (0..3)
+                .map(|_| FChunkPart::new(guid, 0, part_size, 0))
+                .collect(),
+            ..Default::default()
+        };
+
+        let parsed = reparse(&list(vec![file])).unwrap();
+
+        let file = &parsed.entries()[0];
+        let offsets = file
+            .chunk_parts()
+            .iter()
+            .map(|part| part.file_offset())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 0x8000_0000, 0x1_0000_0000]);
+        assert_eq!(file.file_size(), 0x1_8000_0000);
+    }
+
+    #[test]
+    fn version_2_lists_write_one_sha256_per_entry() {
+        let with_hash = FFileManifest {
+            filename: "a.bin".to_owned(),
+            hash_sha256: Some(UnknownHash::new([0x22; SHA256_DIGEST_SIZE])),
+            ..Default::default()
+        };
+        let without_hash = FFileManifest {
+            filename: "b.bin".to_owned(),
+            mime_type: Some("text/plain".to_owned()),
+            ..Default::default()
+        };
+        let mut list = list(vec![with_hash, without_hash]);
+        list._version = 2;
+
+        let parsed = reparse(&list).unwrap();
+
+        let entries = parsed.entries();
+        assert_eq!(entries[1].filename(), "b.bin");
+        assert_eq!(entries[1].mime_type(), Some("text/plain"));
+        let sha256 = |entry: &FFileManifest| entry.hash_sha256.as_ref().unwrap().data;
+        assert_eq!(sha256(&entries[0]), [0x22; SHA256_DIGEST_SIZE]);
+        assert_eq!(sha256(&entries[1]), [0; SHA256_DIGEST_SIZE]);
+    }
+
+    #[test]
+    fn version_0_lists_have_no_hash_or_mime_columns() {
+        let file = FFileManifest {
+            filename: "a.bin".to_owned(),
+            hash_md5: Some(UnknownHash::new([0x11; 16])),
+            mime_type: Some("text/plain".to_owned()),
+            hash_sha256: Some(UnknownHash::new([0x22; SHA256_DIGEST_SIZE])),
+            ..Default::default()
+        };
+        let list = list(vec![file]);
+        let mut writer = ByteWriter::new();
+        list.write(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let parsed = reparse(&list).unwrap();
+
+        assert_eq!(parsed._size as usize, bytes.len());
+        assert_eq!(parsed.write_size(), bytes.len());
+        let file = &parsed.entries()[0];
+        assert_eq!(file.filename(), "a.bin");
+        assert!(file.hash_md5.is_none());
+        assert!(file.mime_type().is_none());
+        assert!(file.hash_sha256.is_none());
+    }
+
+    #[test]
+    fn unknown_versions_keep_per_file_install_tags() {
+        let tagged = |filename: &str, tags: &[&str]| FFileManifest {
+            filename: filename.to_owned(),
+            install_tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut list = list(vec![
+            tagged("a.bin", &["hd", "fr"]),
+            tagged("b.bin", &["fr"]),
+        ]);
+        list._version = LATEST_FILE_LIST_VERSION + 1;
+        let mut writer = ByteWriter::new();
+        list.write(&mut writer);
+
+        let mut warnings = vec![];
+        let mut reader = ByteReader::new(writer.into_bytes());
+        let parsed =
+            FFileManifestList::parse(&mut reader, &ParseOptions::default(), &mut warnings).unwrap();
+
+        assert_eq!(
+            warnings,
+            [ParseWarning::UnknownSectionVersion {
+                section: "FileManifestList",
+                version: LATEST_FILE_LIST_VERSION + 1,
+            }]
+        );
+        assert_eq!(parsed.entries()[0].install_tags(), &["hd", "fr"]);
+        assert_eq!(parsed.entries()[1].install_tags(), &["fr"]);
+    }
+
+    #[test]
+    fn version_1_lists_with_some_mime_types_pass_the_size_check() {
+        let with_mime = FFileManifest {
+            filename: "a.txt".to_owned(),
+            mime_type: Some("text/plain".to_owned()),
+            ..Default::default()
+        };
+        let without_mime = FFileManifest {
+            filename: "b.bin".to_owned(),
+            ..Default::default()
+        };
+        let mut list = list(vec![with_mime, without_mime]);
+        list._version = 1;
+
+        let parsed = reparse(&list).unwrap();
+
+        assert_eq!(parsed._size as usize, list.write_size());
+        assert_eq!(parsed.entries()[0].mime_type(), Some("text/plain"));
+        assert_eq!(parsed.entries()[1].mime_type(), None);
+        assert_eq!(parsed.entries()[1].filename(), "b.bin");
+    }
+
+    fn parse_filename(filename: &str, options: &ParseOptions) -> ParseResult<String> {
+        let file = FFileManifest {
+            filename: filename.to_owned(),
+            ..Default::default()
+        };
+        let mut writer = ByteWriter::new();
+        list(vec![file]).write(&mut writer);
+
+        let mut reader = ByteReader::new(writer.into_bytes());
+        reader.set_lenient_strings(options.lenient_strings);
+        let parsed = FFileManifestList::parse(&mut reader, options, &mut vec![])?;
+        Ok(parsed.entries()[0].filename().to_owned())
+    }
+
+    #[test]
+    fn sanitized_filenames_reject_control_characters() {
+        let sanitize = ParseOptions {
+            sanitize_filenames: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse_filename("a\x07.bin", &ParseOptions::default()).unwrap(),
+            "a\x07.bin"
+        );
+        assert!(matches!(
+            parse_filename("a\x07.bin", &sanitize),
+            Err(ParseError::InvalidData)
+        ));
+        assert!(matches!(
+            parse_filename("dé.bin\u{FFFD}", &sanitize),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(parse_filename("dé.bin", &sanitize).unwrap(), "dé.bin");
+    }
+
+    #[test]
+    fn interior_nuls_never_reach_the_filename() {
+        let sanitize = |lenient_strings| ParseOptions {
+            sanitize_filenames: true,
+            lenient_strings,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            parse_filename("a.bin\0/../../b.bin", &sanitize(false)),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(
+            parse_filename("a.bin\0/../../b.bin", &sanitize(true)).unwrap(),
+            "a.bin"
+        );
+
+        // Non-ASCII filenames are stored as UTF-16
+        assert!(matches!(
+            parse_filename("dé.bin\0/../x", &sanitize(false)),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(
+            parse_filename("dé.bin\0/../x", &sanitize(true)).unwrap(),
+            "dé.bin"
+        );
+    }
+}
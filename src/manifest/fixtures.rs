@@ -0,0 +1,79 @@
+//! Small manifests shared by the unit tests
+
+use std::collections::HashMap;
+
+use super::{
+    builder::{ChunkingStrategy, ManifestBuilder},
+    header::{FManifestHeader, MANIFEST_MAGIC},
+    shared::{
+        EFeatureLevel, EManifestStorageFlags, FGuid, UnknownHash, MD5_DIGEST_SIZE,
+        SHA256_DIGEST_SIZE,
+    },
+    FManifest,
+};
+
+/// Chunk size used by the fixtures, small enough for a few bytes of data to span several chunks
+pub const CHUNK_SIZE: u32 = 4;
+
+/// Builds a manifest holding `files`, split into `CHUNK_SIZE` chunks,
+/// along with the data of every chunk
+pub fn manifest_with_data(files: &[(&str, &[u8])]) -> (FManifest, HashMap<FGuid, Vec<u8>>) {
+    let mut builder = ManifestBuilder::new("TestApp", "1.0.0").app_id(42);
+
+    for (filename, data) in files {
+        builder
+            .add_file(filename, data, ChunkingStrategy::FixedSize(CHUNK_SIZE))
+            .unwrap();
+    }
+
+    builder.build()
+}
+
+/// Same as `manifest_with_data`, without the chunk data
+pub fn manifest(files: &[(&str, &[u8])]) -> FManifest {
+    manifest_with_data(files).0
+}
+
+/// Serializes `manifest` and parses it back, so its sizes and versions are the ones a parsed manifest has
+pub fn reparsed(manifest: &FManifest) -> FManifest {
+    super::parse(&manifest.serialize().unwrap()).unwrap()
+}
+
+/// Upgrades the file list of `manifest` to version 2 and gives every file an MD5, SHA256 and MIME type
+pub fn add_extra_hashes(manifest: &mut FManifest) {
+    manifest.file_list._version = 2;
+
+    for entry in manifest.file_list.entries.iter_mut() {
+        entry.hash_md5 = Some(UnknownHash::new([0x11; MD5_DIGEST_SIZE]));
+        entry.hash_sha256 = Some(UnknownHash::new([0x22; SHA256_DIGEST_SIZE]));
+        entry.mime_type = Some("application/octet-stream".to_owned());
+    }
+}
+
+/// Gives `manifest` the feature level `level` everywhere it is stored
+pub fn set_feature_level(manifest: &mut FManifest, level: EFeatureLevel) {
+    manifest.header = FManifestHeader::new(
+        MANIFEST_MAGIC,
+        0,
+        0,
+        0,
+        Default::default(),
+        manifest.header.stored_as(),
+        level,
+    );
+    manifest.meta.feature_level = level;
+    manifest.chunk_list._manifest_version = level;
+}
+
+/// Changes how `manifest` is stored once serialized
+pub fn set_stored_as(manifest: &mut FManifest, stored_as: EManifestStorageFlags) {
+    manifest.header = FManifestHeader::new(
+        MANIFEST_MAGIC,
+        0,
+        0,
+        0,
+        Default::default(),
+        stored_as,
+        manifest.header.version(),
+    );
+}
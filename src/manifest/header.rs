@@ -1,16 +1,38 @@
 use std::io::Read;
 
-use crate::{error::ParseError, reader::ByteReader, ParseResult};
+use crate::{
+    error::{ParseError, ParseWarning},
+    reader::ByteReader,
+    ParseResult,
+};
 
 use super::{
     shared::{EFeatureLevel, EManifestStorageFlags, FSHAHash},
     FManifestParser,
 };
-use flate2::read::ZlibDecoder;
+use flate2::read::{GzDecoder, ZlibDecoder};
 
 pub const MANIFEST_MAGIC: u32 = 0x44BEC00C;
 
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Size of the first header layout, which ends with the storage flag and has no version field
+const HEADER_SIZE_WITHOUT_VERSION: u32 = 4 + 4 + 4 + 4 + 20 + 1;
+
+/// Whether `declared` looks like the real size truncated to the 32-bit field, given the `available` bytes:
+/// what is left over is then a non-zero multiple of 4GiB
+#[cfg(target_pointer_width = "64")]
+fn size_overflowed(declared: u32, available: usize) -> bool {
+    let extra = (available as u64).saturating_sub(declared as u64);
+    extra > 0 && extra.is_multiple_of(1 << 32)
+}
+
+/// The header stored before the manifest body.
+///
+/// The size fields are 32 bits in every known version, there is no 64-bit variant:
+/// a body over 4GiB can't be described and is rejected when parsing.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FManifestHeader {
     magic: u32,
     header_size: u32,
@@ -23,7 +45,8 @@ pub struct FManifestHeader {
 
 impl FManifestHeader {
     pub fn parse(manifest: &mut FManifestParser) -> ParseResult<(FManifestHeader, ByteReader)> {
-        manifest.reader.seek(0);
+        let offset = manifest.offset.unwrap_or(0);
+        manifest.reader.seek(offset);
         let magic = manifest.reader.read()?;
 
         if magic != MANIFEST_MAGIC {
@@ -34,9 +57,15 @@ impl FManifestHeader {
             return Err(ParseError::InvalidMagic);
         }
 
-        let header_size = manifest.reader.read()?;
+        let header_size: u32 = manifest.reader.read()?;
+        let available_size = manifest.reader.length() - offset;
+        if header_size as usize > available_size {
+            return Err(ParseError::SizeMismatch);
+        }
+
         let data_size_uncompressed = manifest.reader.read()?;
         let data_size_compressed = manifest.reader.read()?;
+        // The format has no header hash type, it is always a 20 byte SHA1
         let header_hash = manifest.reader.read()?;
 
         eprintln!(
@@ -44,29 +73,85 @@ impl FManifestHeader {
             header_size, data_size_uncompressed, data_size_compressed, manifest.reader.length()
         );
 
-        let stored_as = EManifestStorageFlags::try_from(manifest.reader.read::<u8>()?)
-            .map_err(|_| ParseError::InvalidStorageFlag)?;
-        let version =
-            EFeatureLevel::from_i32(manifest.reader.read()?).ok_or(ParseError::InvalidData)?;
+        let raw_stored_as = manifest.reader.read::<u8>()?;
+        let raw_version = if header_size == HEADER_SIZE_WITHOUT_VERSION {
+            None
+        } else {
+            Some(manifest.reader.read::<i32>()?)
+        };
 
-        if header_size != manifest.reader.tell() as u32 {
+        // Out of range values right after the hash mean we didn't read it with the right size
+        let (stored_as, version) = match (
+            EManifestStorageFlags::from_u8(raw_stored_as),
+            raw_version.map(EFeatureLevel::from_i32),
+        ) {
+            (Some(stored_as), Some(Some(version))) => (stored_as, version),
+            // Like Unreal, headers predating the version field are treated as the first UObject format
+            (Some(stored_as), None) => {
+                manifest.warnings.push(ParseWarning::InferredFeatureLevel(
+                    EFeatureLevel::StoredAsCompressedUClass,
+                ));
+                (stored_as, EFeatureLevel::StoredAsCompressedUClass)
+            }
+            _ => return Err(ParseError::HeaderMisaligned),
+        };
+
+        if header_size as usize != manifest.reader.tell() - offset {
             eprintln!(
                 "Header size mismatch: expected {}, got {}",
                 header_size,
-                manifest.reader.tell()
+                manifest.reader.tell() - offset
             );
             return Err(ParseError::OffsetMismatch);
         }
 
-        let remaining_data_size = manifest.reader.length() - header_size as usize;
+        let remaining_data_size = available_size - header_size as usize;
         eprintln!(
             "About to read {} bytes of manifest data (expected compressed size: {})",
             remaining_data_size, data_size_compressed
         );
 
-        let data = manifest.reader.read_bytes(remaining_data_size)?; //actual manifest data
+        if data_size_compressed as usize > remaining_data_size {
+            return Err(ParseError::SizeMismatch);
+        }
+
+        // Embedded manifests are followed by their container, so this can't be told for them.
+        // Only 64-bit hosts can hold more than 4GiB of data, elsewhere the check never triggers.
+        #[cfg(target_pointer_width = "64")]
+        if manifest.offset.is_none() && size_overflowed(data_size_compressed, remaining_data_size) {
+            return Err(ParseError::Overflow);
+        }
+
+        let data = manifest.reader.read_bytes(data_size_compressed as usize)?; //actual manifest data
+
+        // What follows an embedded manifest belongs to its container
+        let trailing_data = if manifest.offset.is_some() {
+            vec![]
+        } else {
+            manifest.reader.read_remaining()
+        };
+        if !trailing_data.is_empty() {
+            let is_padding = trailing_data.iter().all(|byte| *byte == 0);
+            if !is_padding || !manifest.options.allow_trailing_padding {
+                return Err(ParseError::SizeMismatch);
+            }
+            manifest
+                .warnings
+                .push(ParseWarning::TrailingPadding(trailing_data.len()));
+        }
         let proper_data = if stored_as == EManifestStorageFlags::Compressed {
-            let mut decoder = ZlibDecoder::new(&data[..]);
+            if data_size_uncompressed > manifest.options.max_uncompressed_size {
+                return Err(ParseError::InvalidData);
+            }
+
+            // Epic uses zlib, but some pipelines recompress bodies with gzip, recognizable by its magic
+            let decoder: Box<dyn Read> = if data.starts_with(&GZIP_MAGIC) {
+                Box::new(GzDecoder::new(&data[..]))
+            } else {
+                Box::new(ZlibDecoder::new(&data[..]))
+            };
+            // Don't inflate past the declared size, the mismatch is reported below
+            let mut decoder = decoder.take(data_size_uncompressed as u64 + 1);
             let mut buffer: Vec<u8> = Vec::with_capacity(data_size_uncompressed as usize);
             let length = decoder
                 .read_to_end(&mut buffer)
@@ -159,10 +244,20 @@ impl FManifestHeader {
         writer.write_bytes(manifest_data);
     }
 
+    /// Returns how many bytes `write_with_data` produces before the manifest data
+    pub fn write_size(&self) -> usize {
+        4 + 4 + 4 + 4 + 20 + 1 + 4
+    }
+
     pub fn version(&self) -> EFeatureLevel {
         self.version
     }
 
+    /// Whether the header predates the version field, `version` is then assumed rather than stored
+    pub fn version_inferred(&self) -> bool {
+        self.header_size == HEADER_SIZE_WITHOUT_VERSION
+    }
+
     pub fn data_size_uncompressed(&self) -> u32 {
         self.data_size_uncompressed
     }
@@ -187,3 +282,165 @@ impl FManifestHeader {
         self.header_size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{fixtures, ParseOptions};
+
+    fn manifest_bytes() -> Vec<u8> {
+        fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap()
+    }
+
+    fn padding_options() -> ParseOptions {
+        ParseOptions {
+            allow_trailing_padding: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn zero_padding_is_accepted_when_allowed() {
+        let mut bytes = manifest_bytes();
+        bytes.extend_from_slice(&[0; 16]);
+
+        let (_, warnings) = FManifestParser::with_options(&bytes, padding_options())
+            .parse_with_warnings()
+            .unwrap();
+        assert!(warnings.contains(&ParseWarning::TrailingPadding(16)));
+
+        assert!(matches!(
+            FManifestParser::new(&bytes).parse(),
+            Err(ParseError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn non_zero_trailing_bytes_are_rejected() {
+        let mut bytes = manifest_bytes();
+        bytes.extend_from_slice(&[0, 0, 1, 0]);
+
+        assert!(matches!(
+            FManifestParser::with_options(&bytes, padding_options()).parse(),
+            Err(ParseError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn longer_header_hashes_are_reported_as_misaligned() {
+        let mut bytes = manifest_bytes();
+        // Pretend the header hash is a 32 byte SHA256 instead of a 20 byte SHA1
+        let header_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) + 12;
+        bytes[4..8].copy_from_slice(&header_size.to_le_bytes());
+        bytes.splice(36..36, [0xAB; 12]);
+
+        assert!(matches!(
+            FManifestParser::new(&bytes).parse(),
+            Err(ParseError::HeaderMisaligned)
+        ));
+    }
+
+    #[test]
+    fn oversized_header_sizes_are_rejected() {
+        let mut bytes = manifest_bytes();
+        let oversized = bytes.len() as u32 + 1;
+        bytes[4..8].copy_from_slice(&oversized.to_le_bytes());
+
+        assert!(matches!(
+            FManifestParser::new(&bytes).parse(),
+            Err(ParseError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn gzip_wrapped_bodies_are_detected() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let bytes = manifest_bytes();
+        let header_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        let mut body = Vec::new();
+        ZlibDecoder::new(&bytes[header_size..])
+            .read_to_end(&mut body)
+            .unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let gzip_body = encoder.finish().unwrap();
+
+        let mut gzip_bytes = bytes[..header_size].to_vec();
+        gzip_bytes[12..16].copy_from_slice(&(gzip_body.len() as u32).to_le_bytes());
+        gzip_bytes.extend_from_slice(&gzip_body);
+
+        let parsed = FManifestParser::new(&gzip_bytes).parse().unwrap();
+        assert_eq!(parsed.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[test]
+    fn absurd_uncompressed_sizes_are_rejected_before_allocating() {
+        let mut bytes = manifest_bytes();
+        let uncompressed_size = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+
+        let limited = |max_uncompressed_size| ParseOptions {
+            max_uncompressed_size,
+            ..Default::default()
+        };
+        assert!(
+            FManifestParser::with_options(&bytes, limited(uncompressed_size))
+                .parse()
+                .is_ok()
+        );
+        assert!(matches!(
+            FManifestParser::with_options(&bytes, limited(uncompressed_size - 1)).parse(),
+            Err(ParseError::InvalidData)
+        ));
+
+        bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            FManifestParser::new(&bytes).parse(),
+            Err(ParseError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn headers_without_a_version_field_infer_the_feature_level() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoresChunkFileSizes);
+        let mut bytes = manifest.serialize().unwrap();
+        // Drop the version field, the last 4 bytes of the header
+        bytes.drain(37..41);
+        bytes[4..8].copy_from_slice(&HEADER_SIZE_WITHOUT_VERSION.to_le_bytes());
+
+        let options = ParseOptions {
+            strict_versions: true,
+            ..Default::default()
+        };
+        let (parsed, warnings) = FManifestParser::with_options(&bytes, options)
+            .parse_with_warnings()
+            .unwrap();
+
+        assert!(parsed.header.version_inferred());
+        assert_eq!(
+            parsed.header.version(),
+            EFeatureLevel::StoredAsCompressedUClass
+        );
+        assert_eq!(
+            warnings,
+            [ParseWarning::InferredFeatureLevel(
+                EFeatureLevel::StoredAsCompressedUClass
+            )]
+        );
+        assert_eq!(parsed.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn sizes_off_by_a_multiple_of_4gib_look_overflowed() {
+        assert!(size_overflowed(10, 10 + (1 << 32)));
+        assert!(size_overflowed(0, 2 << 32));
+        assert!(!size_overflowed(10, 10));
+        assert!(!size_overflowed(10, 11 + (1 << 32)));
+    }
+}
@@ -1,28 +1,41 @@
-use crate::{error::ParseError, reader::ByteReader, ParseResult};
+use crate::{
+    error::{ParseError, ParseWarning},
+    reader::ByteReader,
+    ParseResult,
+};
 
-use super::shared::EFeatureLevel;
+use super::{shared::EFeatureLevel, ParseOptions};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FManifestMeta {
-    feature_level: EFeatureLevel,
-    b_is_file_data: bool,
-    app_id: u32,
-    app_name: String,
-    build_version: String,
-    launch_exe: String,
-    launch_command: String,
-    prerequisites: Vec<String>,
-    prereq_name: String,
-    prereq_path: String,
-    prereq_args: String,
-    build_id: Option<String>,
-    prereq_ids: Vec<String>,
-    uninstall_action_path: Option<String>,
-    uninstall_action_args: Option<String>,
+    #[serde(default)]
+    pub(crate) _size: u32,
+    #[serde(default)]
+    pub(crate) _data_version: u8,
+    pub(crate) feature_level: EFeatureLevel,
+    pub(crate) b_is_file_data: bool,
+    pub(crate) app_id: u32,
+    pub(crate) app_name: String,
+    pub(crate) build_version: String,
+    pub(crate) launch_exe: String,
+    pub(crate) launch_command: String,
+    pub(crate) prerequisites: Vec<String>,
+    pub(crate) prereq_name: String,
+    pub(crate) prereq_path: String,
+    pub(crate) prereq_args: String,
+    pub(crate) build_id: Option<String>,
+    pub(crate) prereq_ids: Vec<String>,
+    pub(crate) uninstall_action_path: Option<String>,
+    pub(crate) uninstall_action_args: Option<String>,
 }
 
 impl FManifestMeta {
-    pub fn parse(reader: &mut ByteReader) -> ParseResult<FManifestMeta> {
+    pub fn parse(
+        reader: &mut ByteReader,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> ParseResult<FManifestMeta> {
         let meta_size = reader.read::<u32>()?;
         let data_version = reader.read::<u8>()?;
 
@@ -36,12 +49,16 @@ impl FManifestMeta {
         let launch_exe = reader.read()?;
         let launch_command = reader.read()?;
 
+        // Epic's FManifestMeta serializer writes the prerequisite ids before the name, path and args,
+        // in every data version. Legendary reads them in the same order.
         let prereq_ids = reader.read_array(|reader| reader.read())?;
         let prereq_name = reader.read()?;
         let prereq_path = reader.read()?;
         let prereq_args = reader.read()?;
 
         let mut metadata = FManifestMeta {
+            _size: meta_size,
+            _data_version: data_version,
             feature_level,
             b_is_file_data,
             app_id,
@@ -73,11 +90,20 @@ impl FManifestMeta {
 
         if reader.tell() != meta_size as usize {
             println!(
-                "Metadata size mismatch, {} bytes are missing, version : {}",
-                meta_size - reader.tell() as u32,
+                "Metadata size mismatch: expected {} but got {}, version : {}",
+                meta_size,
+                reader.tell(),
                 data_version
             );
-            return Err(ParseError::InvalidData);
+            if !options.lenient_sizes {
+                return Err(ParseError::InvalidData);
+            }
+            warnings.push(ParseWarning::SectionSizeMismatch {
+                section: "Meta",
+                expected: meta_size,
+                actual: reader.tell(),
+            });
+            reader.seek(meta_size as usize);
         }
 
         Ok(metadata)
@@ -87,47 +113,9 @@ impl FManifestMeta {
     pub fn write(&self, writer: &mut crate::writer::ByteWriter, data_version: u8) {
         use crate::writer::ByteWritable;
 
-        // Calculate the size first by writing to a temporary buffer
-        let mut temp_writer = crate::writer::ByteWriter::new();
-        temp_writer.write(&data_version);
-        temp_writer.write(&self.feature_level);
-        temp_writer.write(&(if self.b_is_file_data { 1u8 } else { 0u8 }));
-        temp_writer.write(&self.app_id);
-        temp_writer.write(&self.app_name);
-        temp_writer.write(&self.build_version);
-        temp_writer.write(&self.launch_exe);
-        temp_writer.write(&self.launch_command);
-        temp_writer.write_array(&self.prereq_ids);
-        temp_writer.write(&self.prereq_name);
-        temp_writer.write(&self.prereq_path);
-        temp_writer.write(&self.prereq_args);
-
-        if data_version >= 1 {
-            if let Some(ref build_id) = self.build_id {
-                temp_writer.write(build_id);
-            } else {
-                temp_writer.write(&String::new());
-            }
-        }
-
-        if data_version >= 2 {
-            if let Some(ref uninstall_action_path) = self.uninstall_action_path {
-                temp_writer.write(uninstall_action_path);
-            } else {
-                temp_writer.write(&String::new());
-            }
-
-            if let Some(ref uninstall_action_args) = self.uninstall_action_args {
-                temp_writer.write(uninstall_action_args);
-            } else {
-                temp_writer.write(&String::new());
-            }
-        }
-
-        let meta_size = (temp_writer.tell() + 4) as u32; // +4 for the size field itself
-
-        // Write the actual data with correct size
-        writer.write(&meta_size);
+        // Reserve the size field and back-patch it once the section is written
+        let start = writer.tell();
+        writer.write(&0u32);
         writer.write(&data_version);
         writer.write(&self.feature_level);
         writer.write(&(if self.b_is_file_data { 1u8 } else { 0u8 }));
@@ -136,6 +124,7 @@ impl FManifestMeta {
         writer.write(&self.build_version);
         writer.write(&self.launch_exe);
         writer.write(&self.launch_command);
+        // Same order as `parse`, the ids come first
         writer.write_array(&self.prereq_ids);
         writer.write(&self.prereq_name);
         writer.write(&self.prereq_path);
@@ -162,6 +151,39 @@ impl FManifestMeta {
                 writer.write(&String::new());
             }
         }
+
+        writer.write_u32_at(start, (writer.tell() - start) as u32); // the size includes the size field itself
+    }
+
+    /// Returns how many bytes `write` produces for the given data version, without writing anything
+    pub fn write_size(&self, data_version: u8) -> usize {
+        use crate::writer::string_size;
+
+        // size, data version, feature level, is file data and app id
+        let mut size = 4 + 1 + 4 + 1 + 4;
+        size += string_size(&self.app_name);
+        size += string_size(&self.build_version);
+        size += string_size(&self.launch_exe);
+        size += string_size(&self.launch_command);
+        size += 4 + self
+            .prereq_ids
+            .iter()
+            .map(|id| string_size(id))
+            .sum::<usize>();
+        size += string_size(&self.prereq_name);
+        size += string_size(&self.prereq_path);
+        size += string_size(&self.prereq_args);
+
+        if data_version >= 1 {
+            size += string_size(self.build_id.as_deref().unwrap_or_default());
+        }
+
+        if data_version >= 2 {
+            size += string_size(self.uninstall_action_path.as_deref().unwrap_or_default());
+            size += string_size(self.uninstall_action_args.as_deref().unwrap_or_default());
+        }
+
+        size
     }
 
     pub fn app_id(&self) -> u32 {
@@ -223,4 +245,49 @@ impl FManifestMeta {
     pub fn is_file_data(&self) -> bool {
         self.b_is_file_data
     }
+
+    /// Returns the version of the meta section itself, unrelated to the feature level
+    pub fn data_version(&self) -> u8 {
+        self._data_version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::ByteWriter;
+
+    #[test]
+    fn prerequisite_ids_are_read_before_name_path_and_args() {
+        // A data version 0 meta section laid out like Epic's serializer writes it
+        let mut writer = ByteWriter::new();
+        writer.write(&0u32);
+        writer.write(&0u8);
+        writer.write(&EFeatureLevel::StoresPrerequisiteIds);
+        writer.write(&0u8);
+        writer.write(&1u32);
+        for string in ["App", "1.0", "App.exe", "-log"] {
+            writer.write(&string.to_string());
+        }
+        writer.write_array(&["prereq-1".to_string(), "prereq-2".to_string()]);
+        for string in ["Redist", "redist.exe", "/quiet"] {
+            writer.write(&string.to_string());
+        }
+        writer.write_u32_at(0, writer.tell() as u32);
+
+        let bytes = writer.into_bytes();
+        let mut reader = ByteReader::new(bytes.clone());
+        let meta =
+            FManifestMeta::parse(&mut reader, &ParseOptions::default(), &mut vec![]).unwrap();
+
+        assert_eq!(meta.launch_command(), "-log");
+        assert_eq!(meta.prereq_ids(), &["prereq-1", "prereq-2"]);
+        assert_eq!(meta.prereq_name(), "Redist");
+        assert_eq!(meta.prereq_path(), "redist.exe");
+        assert_eq!(meta.prereq_args(), "/quiet");
+
+        let mut written = ByteWriter::new();
+        meta.write(&mut written, 0);
+        assert_eq!(written.as_bytes(), bytes);
+    }
 }
@@ -1,22 +1,125 @@
-use crate::{reader::ByteReader, ParseResult};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use crate::{error::ParseWarning, reader::ByteReader, ParseResult};
+
+pub mod builder;
 pub mod chunk_info;
 pub mod chunk_list;
 pub mod chunk_part;
 pub mod chunks;
 pub mod custom_fields;
+pub mod diff;
 pub mod file_manifest;
 pub mod file_manifest_list;
+#[cfg(test)]
+pub(crate) mod fixtures;
 pub mod header;
 pub mod meta;
+pub mod plan;
+pub mod rolling_hash;
 pub mod shared;
+pub mod summary;
+
+/// Parses binary manifest data, the native counterpart of the `parse_manifest` WASM export
+pub fn parse(bytes: &[u8]) -> ParseResult<FManifest> {
+    FManifestParser::new(bytes).parse()
+}
+
+/// Parses binary manifest data, also returning the soft anomalies that didn't prevent parsing
+pub fn parse_with_warnings(bytes: &[u8]) -> ParseResult<(FManifest, Vec<ParseWarning>)> {
+    FManifestParser::new(bytes).parse_with_warnings()
+}
+
+/// Parses only the header and meta of binary manifest data, see `FManifestParser::parse_meta_only`
+pub fn parse_meta_only(
+    bytes: &[u8],
+) -> ParseResult<(header::FManifestHeader, meta::FManifestMeta)> {
+    FManifestParser::new(bytes).parse_meta_only()
+}
+
+/// Converts a manifest to the JSON representation used by the WASM API
+pub fn to_json(manifest: &FManifest) -> Result<String, crate::error::Error> {
+    Ok(serde_json::to_string_pretty(manifest)?)
+}
+
+/// Reads a manifest from the JSON representation used by the WASM API
+pub fn from_json(json: &str) -> Result<FManifest, crate::error::Error> {
+    Ok(serde_json::from_str(json)?)
+}
+
+/// Returns a JSON Schema describing the JSON representation of a manifest,
+/// so the JS side can generate types for what `parse_manifest` returns
+#[cfg(feature = "schemars")]
+pub fn json_schema() -> Result<String, crate::error::Error> {
+    let schema = schemars::schema_for!(FManifest);
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+/// Creates binary manifest data from JSON, the native counterpart of the `create_manifest` WASM export
+pub fn create(json: &str) -> Result<Vec<u8>, crate::error::Error> {
+    Ok(from_json(json)?.serialize()?)
+}
 
 pub struct FManifestParser {
     pub data: Vec<u8>,
     pub reader: ByteReader,
+    pub options: ParseOptions,
+    /// Where the manifest starts, when it is embedded in a bigger container
+    pub offset: Option<usize>,
+    /// Soft anomalies found so far, see `parse_with_warnings`
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// The default `ParseOptions::max_uncompressed_size`, far above the size of real manifests
+pub const DEFAULT_MAX_UNCOMPRESSED_SIZE: u32 = 512 * 1024 * 1024;
+
+/// Options controlling how strict the FManifestParser is
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Accept zero bytes after the declared manifest data, as added by pipelines padding files to a block size.
+    /// Non-zero trailing bytes are still rejected.
+    pub allow_trailing_padding: bool,
+    /// Keep the original compressed body on the FManifest, see `FManifest::compressed_body`
+    pub keep_compressed_body: bool,
+    /// Log section size mismatches instead of failing, and skip to where the section should end.
+    /// Meant for inspecting unknown manifest variants, the result may be partially wrong.
+    pub lenient_sizes: bool,
+    /// Read UTF-8 and UTF-16 strings without a trailing nul or with interior nuls lossily
+    /// instead of failing, cutting them at the first nul
+    pub lenient_strings: bool,
+    /// Largest uncompressed body size accepted, checked before anything is allocated
+    /// so a forged size field can't force a huge allocation
+    pub max_uncompressed_size: u32,
+    /// Reject filenames containing control characters or characters that were invalid in the
+    /// stored encoding, such as overlong UTF-8 read with `lenient_strings`. Interior nuls already
+    /// fail the parse, or end the filename with `lenient_strings`.
+    /// Meant for extractors, alongside `FManifest::resolve_install_path`.
+    pub sanitize_filenames: bool,
+    /// Keep the bytes following the custom fields in `FManifest::unknown_trailing`, so a section
+    /// added by a newer format survives being serialized again. They are dropped otherwise.
+    pub keep_unknown_trailing: bool,
+    /// Fail with `ParseError::InconsistentVersions` when the feature level disagrees with the section
+    /// versions, instead of only reporting a `ParseWarning::InconsistentVersions`
+    pub strict_versions: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            allow_trailing_padding: false,
+            keep_compressed_body: false,
+            lenient_sizes: false,
+            lenient_strings: false,
+            max_uncompressed_size: DEFAULT_MAX_UNCOMPRESSED_SIZE,
+            sanitize_filenames: false,
+            keep_unknown_trailing: false,
+            strict_versions: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct FManifest {
     pub header: header::FManifestHeader,
     pub meta: meta::FManifestMeta,
@@ -24,33 +127,223 @@ pub struct FManifest {
     pub file_list: file_manifest_list::FFileManifestList,
     pub custom_fields: custom_fields::FCustomFields,
     pub data: Vec<u8>,
+    /// Body bytes after the custom fields, only kept when parsed with `keep_unknown_trailing`.
+    /// Written back verbatim after the custom fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_trailing: Vec<u8>,
+    /// The original compressed body, only kept when parsed with `parse_keep_compressed`
+    #[serde(skip)]
+    compressed_body: Option<Vec<u8>>,
 }
 
 impl FManifestParser {
     pub fn new(data: &[u8]) -> FManifestParser {
+        FManifestParser::with_options(data, ParseOptions::default())
+    }
+
+    pub fn with_options(data: &[u8], options: ParseOptions) -> FManifestParser {
         FManifestParser {
             data: data.to_vec(),
             reader: ByteReader::new(data.to_vec()),
+            options,
+            offset: None,
+            warnings: vec![],
         }
     }
 
-    pub fn parse(mut self) -> ParseResult<FManifest> {
+    /// Creates a parser for a manifest embedded at `offset` in a bigger container.
+    /// Bytes after the manifest are ignored as they belong to the container.
+    pub fn new_at_offset(data: &[u8], offset: usize) -> FManifestParser {
+        FManifestParser {
+            offset: Some(offset),
+            ..FManifestParser::new(data)
+        }
+    }
+
+    pub fn parse(self) -> ParseResult<FManifest> {
+        self.parse_with_warnings().map(|(manifest, _)| manifest)
+    }
+
+    /// Parses the header and the meta section, then stops.
+    /// The meta is the first section of the body, so this skips the cost of the chunk and file lists
+    /// when only app metadata is needed. The body still gets decompressed and hash checked.
+    pub fn parse_meta_only(
+        mut self,
+    ) -> ParseResult<(header::FManifestHeader, meta::FManifestMeta)> {
         let (header, mut reader) = header::FManifestHeader::parse(&mut self)?;
+        reader.set_lenient_strings(self.options.lenient_strings);
 
-        let meta = meta::FManifestMeta::parse(&mut reader)?;
-        let chunk_header = chunk_list::FChunkList::parse(&mut reader, header.version())?;
-        let file_list = file_manifest_list::FFileManifestList::parse(&mut reader)?;
-        let custom_fields = custom_fields::FCustomFields::parse(&mut reader)?;
+        let meta = meta::FManifestMeta::parse(&mut reader, &self.options, &mut self.warnings)?;
+        check_version_consistency(&header, &meta, None, &self.options, &mut self.warnings)?;
 
-        Ok(FManifest {
+        Ok((header, meta))
+    }
+
+    /// Parses the manifest, also returning the soft anomalies that didn't prevent parsing
+    pub fn parse_with_warnings(mut self) -> ParseResult<(FManifest, Vec<ParseWarning>)> {
+        let (header, mut reader) = header::FManifestHeader::parse(&mut self)?;
+        reader.set_lenient_strings(self.options.lenient_strings);
+
+        let version = header.version().effective();
+        // Treated as `effective()` for all gating decisions
+        if header.version() == shared::EFeatureLevel::BrokenJsonVersion {
+            self.warnings.push(ParseWarning::BrokenJsonVersion);
+        }
+
+        let options = &self.options;
+        let warnings = &mut self.warnings;
+        let meta = meta::FManifestMeta::parse(&mut reader, options, warnings)?;
+        let chunk_header = chunk_list::FChunkList::parse(&mut reader, version, options, warnings)?;
+        let file_list =
+            file_manifest_list::FFileManifestList::parse(&mut reader, options, warnings)?;
+        let custom_fields = custom_fields::FCustomFields::parse(&mut reader, options, warnings)?;
+
+        let mut unknown_trailing = Vec::new();
+        if reader.remaining() > 0 {
+            warnings.push(ParseWarning::UnknownTrailingData(reader.remaining()));
+
+            if options.keep_unknown_trailing {
+                unknown_trailing = reader.read_remaining();
+            }
+        }
+
+        let sections = Some((&chunk_header, &custom_fields));
+        check_version_consistency(&header, &meta, sections, options, warnings)?;
+
+        let offset = self.offset.unwrap_or(0);
+        let body_start = offset + header.header_size() as usize;
+        let body_end = body_start + header.data_size_compressed() as usize;
+
+        let compressed_body = if self.options.keep_compressed_body
+            && header.stored_as() == shared::EManifestStorageFlags::Compressed
+        {
+            Some(self.data[body_start..body_end].to_vec())
+        } else {
+            None
+        };
+
+        // Only keep the manifest itself out of its container
+        let data = if self.offset.is_some() {
+            self.data[offset..body_end].to_vec()
+        } else {
+            self.data
+        };
+
+        let manifest = FManifest {
             header,
             meta,
             chunk_list: chunk_header,
             file_list,
             custom_fields,
-            data: self.data,
-        })
+            data,
+            unknown_trailing,
+            compressed_body,
+        };
+
+        let duplicates = manifest.duplicate_filenames();
+        if !duplicates.is_empty() {
+            self.warnings
+                .push(ParseWarning::DuplicateFilenames(duplicates));
+        }
+
+        let orphan_count =
+            manifest.chunk_list.chunks().len() - manifest.all_required_chunks().len();
+        if orphan_count > 0 {
+            self.warnings.push(ParseWarning::OrphanChunks(orphan_count));
+        }
+
+        for file in manifest.file_list.entries() {
+            if *file.hash() == shared::FSHAHash::default() {
+                self.warnings
+                    .push(ParseWarning::ZeroFileHash(file.filename().to_owned()));
+            }
+        }
+
+        Ok((manifest, self.warnings))
+    }
+
+    /// Parses the manifest while keeping the original compressed body,
+    /// so it can be stored without re-compressing (which may not byte-match)
+    pub fn parse_keep_compressed(mut self) -> ParseResult<FManifest> {
+        self.options.keep_compressed_body = true;
+        self.parse()
+    }
+}
+
+/// Cross-checks the feature level against the section versions and contents it implies,
+/// catching hand-edited or corrupted manifests. The chunk list and custom fields are only checked when given.
+///
+/// Every inconsistency is reported as a `ParseWarning::InconsistentVersions`, or fails the parse
+/// with `strict_versions`.
+fn check_version_consistency(
+    header: &header::FManifestHeader,
+    meta: &meta::FManifestMeta,
+    sections: Option<(&chunk_list::FChunkList, &custom_fields::FCustomFields)>,
+    options: &ParseOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> ParseResult<()> {
+    use crate::error::ParseError;
+    use shared::EFeatureLevel;
+
+    let mut inconsistencies = Vec::new();
+
+    // Headers predating the version field only have an assumed feature level to compare
+    if !header.version_inferred() && header.version() != meta.feature_level() {
+        inconsistencies.push(format!(
+            "header reports feature level {} but meta reports {}",
+            header.version().to_i32(),
+            meta.feature_level().to_i32()
+        ));
+    }
+
+    // The build id is only stored from meta data version 1 onwards
+    let feature_level = meta.feature_level().effective();
+    if feature_level.to_i32() >= EFeatureLevel::UsesBuildTimeGeneratedBuildId.to_i32()
+        && meta.data_version() < 1
+    {
+        inconsistencies.push(format!(
+            "feature level {} stores a build id but the meta section has data version {}",
+            feature_level.to_i32(),
+            meta.data_version()
+        ));
+    }
+
+    if let Some((chunk_list, custom_fields)) = sections {
+        // Chunks used a fixed 1MiB window before variable size chunks, their size is stored from StoresChunkFileSizes
+        let chunk_level = header.version().effective().to_i32();
+        if chunk_level >= EFeatureLevel::StoresChunkFileSizes.to_i32()
+            && chunk_level < EFeatureLevel::VariableSizeChunksWithoutWindowSizeChunkInfo.to_i32()
+        {
+            let variable_chunk = chunk_list.chunks().iter().find(|chunk| {
+                chunk.uncompressed_size() > chunks::chunk_header::LEGACY_CHUNK_WINDOW_SIZE
+            });
+
+            if let Some(chunk) = variable_chunk {
+                inconsistencies.push(format!(
+                    "feature level {} predates variable size chunks but chunk {} has a {} byte window",
+                    chunk_level,
+                    chunk.guid().to_hex_lower(),
+                    chunk.uncompressed_size()
+                ));
+            }
+        }
+
+        if feature_level == EFeatureLevel::Original && !custom_fields.fields.is_empty() {
+            inconsistencies.push(format!(
+                "feature level 0 predates custom fields but {} are stored",
+                custom_fields.fields.len()
+            ));
+        }
+    }
+
+    for reason in inconsistencies {
+        if options.strict_versions {
+            return Err(ParseError::InconsistentVersions(reason));
+        }
+        warnings.push(ParseWarning::InconsistentVersions(reason));
     }
+
+    Ok(())
 }
 
 impl FManifest {
@@ -59,33 +352,32 @@ impl FManifest {
     /// This function recreates the original manifest file structure by writing
     /// each component in the correct order and format.
     pub fn serialize(&self) -> ParseResult<Vec<u8>> {
-        use crate::manifest::shared::{EManifestStorageFlags, FSHAHash};
+        let (uncompressed_data, calculated_hash) = self.serialize_body_with_hash();
+        self.serialize_with_body(uncompressed_data, calculated_hash)
+    }
+
+    /// Same as `serialize`, but writes `hash` in the header instead of computing it.
+    ///
+    /// Meant for reproducing an original manifest whose hash is already known. Nothing checks
+    /// that `hash` matches the body, a wrong one makes the output fail to parse.
+    pub fn serialize_with_hash(&self, hash: shared::FSHAHash) -> ParseResult<Vec<u8>> {
+        self.serialize_with_body(self.serialize_body(), hash)
+    }
+
+    /// Compresses the body if the manifest is stored compressed and writes it after the header
+    fn serialize_with_body(
+        &self,
+        uncompressed_data: Vec<u8>,
+        calculated_hash: shared::FSHAHash,
+    ) -> ParseResult<Vec<u8>> {
+        use crate::manifest::shared::EManifestStorageFlags;
         use crate::writer::{ByteWritable, ByteWriter};
         use flate2::write::ZlibEncoder;
         use flate2::Compression;
         use std::io::Write;
 
-        // Create the manifest data (everything except the header)
-        let mut data_writer = ByteWriter::new();
-
-        // Write meta with appropriate version (assume version 2 for full compatibility)
-        self.meta.write(&mut data_writer, 2);
-
-        // Write chunk list
-        self.chunk_list.write(&mut data_writer);
-
-        // Write file list
-        self.file_list.write(&mut data_writer);
-
-        // Write custom fields
-        self.custom_fields.write(&mut data_writer);
-
-        let uncompressed_data = data_writer.into_bytes();
         let data_size_uncompressed = uncompressed_data.len() as u32;
 
-        // Calculate SHA hash of the uncompressed data before potentially moving it
-        let calculated_hash = FSHAHash::new_from_hashable(&uncompressed_data);
-
         // Compress data if the original was compressed
         let (final_data, data_size_compressed) = match self.header.stored_as() {
             EManifestStorageFlags::Compressed => {
@@ -123,10 +415,1783 @@ impl FManifest {
         Ok(final_writer.into_bytes())
     }
 
+    /// Same as `serialize`, but with the chunk list sorted by GUID so that manifests generated
+    /// from the same chunks always have the same chunk order
+    pub fn serialize_canonical(&self) -> ParseResult<Vec<u8>> {
+        let mut manifest = self.clone();
+        manifest.chunk_list.sort_by_guid();
+        manifest.serialize()
+    }
+
+    /// Serializes every section after the header (meta, chunk list, file list, custom fields), uncompressed
+    pub fn serialize_body(&self) -> Vec<u8> {
+        let mut data_writer = crate::writer::ByteWriter::with_capacity(self.serialized_body_size());
+        self.write_body_sections(|write_section| write_section(&mut data_writer));
+        data_writer.into_bytes()
+    }
+
+    /// Same as `serialize_body`, also returning the SHA1 of the body which is computed
+    /// section by section while it is written, instead of in a second pass
+    pub fn serialize_body_with_hash(&self) -> (Vec<u8>, shared::FSHAHash) {
+        let mut data_writer =
+            crate::writer::HashingWriter::with_capacity(self.serialized_body_size());
+        self.write_body_sections(|write_section| data_writer.section(write_section));
+        data_writer.finish()
+    }
+
+    /// Calls `section` with the writing of every body section, in order
+    fn write_body_sections(
+        &self,
+        mut section: impl FnMut(&dyn Fn(&mut crate::writer::ByteWriter)),
+    ) {
+        // Write meta with appropriate version (assume version 2 for full compatibility)
+        section(&|writer| self.meta.write(writer, 2));
+
+        // Write chunk list
+        section(&|writer| self.chunk_list.write(writer));
+
+        // Write file list
+        section(&|writer| self.file_list.write(writer));
+
+        // Write custom fields
+        section(&|writer| self.custom_fields.write(writer));
+
+        // Sections this crate doesn't know, kept as they were
+        section(&|writer| writer.write_bytes(&self.unknown_trailing));
+    }
+
+    /// Returns the exact length of `serialize`'s output for a manifest stored uncompressed,
+    /// without building it. Compressed manifests are smaller, by an amount only known after compressing.
+    pub fn serialized_size(&self) -> usize {
+        self.header.write_size() + self.serialized_body_size()
+    }
+
+    /// Returns the exact length of `serialize_body`'s output, without building it
+    pub fn serialized_body_size(&self) -> usize {
+        self.meta.write_size(2)
+            + self.chunk_list.write_size()
+            + self.file_list.write_size()
+            + self.custom_fields.write_size()
+            + self.unknown_trailing.len()
+    }
+
+    /// Returns a SHA1 over the canonical uncompressed body, with chunks sorted by GUID and files by name.
+    ///
+    /// Unlike the header hash, this depends neither on how the manifest is stored nor on the order
+    /// of its chunks and files, so it can be used as a cache key for semantically equal manifests.
+    /// The chunk and file list versions are part of the content, as they decide which fields are stored.
+    pub fn content_hash(&self) -> shared::FSHAHash {
+        let mut canonical = self.clone();
+        canonical.chunk_list.sort_by_guid();
+        canonical.file_list.sort_by_filename();
+
+        canonical.serialize_body_with_hash().1
+    }
+
+    /// Returns whether both manifests have the same feature level and the same `content_hash`.
+    ///
+    /// The chunk list, file list and custom fields versions are compared as part of the content.
+    /// How the manifests are stored, the order of their chunks and files, the meta data version
+    /// and the section sizes recorded when they were parsed are ignored.
+    pub fn semantically_eq(&self, other: &FManifest) -> bool {
+        self.header.version() == other.header.version()
+            && self.content_hash() == other.content_hash()
+    }
+
+    /// Serializes the manifest, parses the result and checks it is semantically equal to `self`.
+    ///
+    /// A failure means the writer produces something the reader reads differently,
+    /// so the manifest can't be safely written back.
+    pub fn check_roundtrip(&self) -> ParseResult<()> {
+        let reparsed = parse(&self.serialize()?)?;
+
+        if !self.semantically_eq(&reparsed) {
+            return Err(crate::error::ParseError::RoundtripMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the file hashes in the format `sha1sum -c` reads, one `<sha1>  <filename>` line per file,
+    /// so an install can be verified with coreutils. Paths use forward slashes.
+    pub fn sha1sums(&self) -> String {
+        let mut sums = String::new();
+
+        for file in self.file_list.entries() {
+            sums.push_str(&file.hash().to_hex_lower());
+            sums.push_str("  ");
+            sums.push_str(&file.normalized_filename());
+            sums.push('\n');
+        }
+
+        sums
+    }
+
+    /// Returns a SHA1 over the sorted (filename, file hash) pairs of the manifest.
+    ///
+    /// Two builds installing the same files share a fingerprint no matter how they are chunked,
+    /// which makes it a cheap value to store for detecting a modified install.
+    pub fn install_fingerprint(&self) -> shared::FSHAHash {
+        let mut files: Vec<(&str, [u8; shared::SHA1_DIGEST_SIZE])> = self
+            .file_list
+            .entries()
+            .iter()
+            .map(|file| (file.filename(), file.hash().data()))
+            .collect();
+        files.sort();
+
+        let mut data = Vec::new();
+        for (filename, hash) in files {
+            data.extend_from_slice(filename.as_bytes());
+            // Separate the name from the hash so different pairs can't produce the same bytes
+            data.push(0);
+            data.extend_from_slice(&hash);
+        }
+
+        shared::FSHAHash::new_from_hashable(data)
+    }
+
+    /// Returns the chunks of this manifest that `source` doesn't have, i.e. what has to be downloaded
+    /// to update an install of `source` to this manifest
+    pub fn new_chunks(&self, source: &FManifest) -> Vec<&chunk_info::FChunkInfo> {
+        let existing: HashSet<&shared::FGuid> = source
+            .chunk_list
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.guid())
+            .collect();
+
+        self.chunk_list
+            .chunks()
+            .iter()
+            .filter(|chunk| !existing.contains(chunk.guid()))
+            .collect()
+    }
+
+    /// Returns the files present in both manifests whose SHA1 hash differs from the one in `old`
+    pub fn files_changed_since(&self, old: &FManifest) -> Vec<&file_manifest::FFileManifest> {
+        let old_files: HashMap<&str, &file_manifest::FFileManifest> = old
+            .file_list
+            .entries()
+            .iter()
+            .map(|file| (file.filename(), file))
+            .collect();
+
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| {
+                old_files
+                    .get(file.filename())
+                    .is_some_and(|old_file| old_file.hash() != file.hash())
+            })
+            .collect()
+    }
+
+    /// Returns the files of this manifest that `old` doesn't have
+    pub fn files_added_since(&self, old: &FManifest) -> Vec<&file_manifest::FFileManifest> {
+        let old_names = old.file_names();
+
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| !old_names.contains(file.filename()))
+            .collect()
+    }
+
+    /// Returns the files of `old` this manifest doesn't have anymore
+    pub fn files_removed_since<'a>(
+        &self,
+        old: &'a FManifest,
+    ) -> Vec<&'a file_manifest::FFileManifest> {
+        let names = self.file_names();
+
+        old.file_list
+            .entries()
+            .iter()
+            .filter(|file| !names.contains(file.filename()))
+            .collect()
+    }
+
+    fn file_names(&self) -> HashSet<&str> {
+        self.file_list
+            .entries()
+            .iter()
+            .map(|file| file.filename())
+            .collect()
+    }
+
+    /// Returns the chunks referenced by at least one file, in chunk list order.
+    /// This is the download set of a full install, which excludes orphaned chunks.
+    pub fn all_required_chunks(&self) -> Vec<&chunk_info::FChunkInfo> {
+        let referenced: HashSet<&shared::FGuid> = self
+            .file_list
+            .entries()
+            .iter()
+            .flat_map(|file| file.chunk_parts())
+            .map(|part| part.guid())
+            .collect();
+
+        self.chunk_list
+            .chunks()
+            .iter()
+            .filter(|chunk| referenced.contains(chunk.guid()))
+            .collect()
+    }
+
+    /// Returns the download size, in compressed bytes, of a full install
+    pub fn full_install_size(&self) -> u64 {
+        self.all_required_chunks()
+            .iter()
+            .map(|chunk| chunk.compressed_size().max(0) as u64)
+            .sum()
+    }
+
+    /// Returns the `n` chunks with the largest compressed size, largest first
+    pub fn largest_chunks(&self, n: usize) -> Vec<&chunk_info::FChunkInfo> {
+        let mut chunks: Vec<&chunk_info::FChunkInfo> = self.chunk_list.chunks().iter().collect();
+        let by_size_desc = |a: &&chunk_info::FChunkInfo, b: &&chunk_info::FChunkInfo| {
+            b.compressed_size().cmp(&a.compressed_size())
+        };
+
+        // Partition around the n-th largest first so only the top n have to be fully sorted
+        if n < chunks.len() {
+            chunks.select_nth_unstable_by(n, by_size_desc);
+            chunks.truncate(n);
+        }
+        chunks.sort_by(by_size_desc);
+
+        chunks
+    }
+
+    /// Returns a (filename, GUID) pair for every chunk part referencing a chunk that isn't in the chunk list
+    pub fn dangling_chunk_part_refs(&self) -> Vec<(&str, &shared::FGuid)> {
+        let known_chunks = self
+            .chunk_list
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.guid())
+            .collect::<HashSet<_>>();
+
+        self.file_list
+            .entries()
+            .iter()
+            .flat_map(|file| {
+                file.chunk_parts()
+                    .iter()
+                    .filter(|part| !known_chunks.contains(part.guid()))
+                    .map(move |part| (file.filename(), part.guid()))
+            })
+            .collect()
+    }
+
+    /// Returns how many chunks are in each group, indexed by group number.
+    /// Chunks are spread over the groups by hash, so a lopsided histogram points at a broken generator.
+    pub fn group_histogram(&self) -> [u32; 256] {
+        let mut histogram = [0u32; 256];
+
+        for chunk in self.chunk_list.chunks() {
+            histogram[chunk.group_num() as usize] += 1;
+        }
+
+        histogram
+    }
+
+    /// Returns the files whose stored size differs from what their chunk parts add up to,
+    /// see `FFileManifest::has_consistent_file_size`
+    pub fn file_size_mismatches(&self) -> Vec<&file_manifest::FFileManifest> {
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| !file.has_consistent_file_size())
+            .collect()
+    }
+
+    /// Returns the chunks with an all-zero SHA1 hash in a manifest whose feature level requires one.
+    /// Manifests older than `StoresChunkDataShaHashes` never return anything.
+    pub fn chunks_missing_sha(&self) -> Vec<&chunk_info::FChunkInfo> {
+        if self.header.version().effective().to_i32()
+            < shared::EFeatureLevel::StoresChunkDataShaHashes.to_i32()
+        {
+            return vec![];
+        }
+
+        self.chunk_list
+            .chunks()
+            .iter()
+            .filter(|chunk| *chunk.sha_hash() == shared::FSHAHash::default())
+            .collect()
+    }
+
+    /// Returns the download size, in compressed bytes, of updating an install of `source` to this manifest
+    pub fn patch_size_from(&self, source: &FManifest) -> u64 {
+        self.new_chunks(source)
+            .iter()
+            .map(|chunk| chunk.compressed_size().max(0) as u64)
+            .sum()
+    }
+
+    /// Computes `patch_size_from` for every source build, keyed by the build id of each source
+    /// (or its build version for manifests that don't have a build id)
+    pub fn patch_sizes_from(&self, sources: &[FManifest]) -> HashMap<String, u64> {
+        sources
+            .iter()
+            .map(|source| {
+                let key = source
+                    .meta
+                    .build_id()
+                    .cloned()
+                    .unwrap_or_else(|| source.meta.build_version().to_owned());
+
+                (key, self.patch_size_from(source))
+            })
+            .collect()
+    }
+
+    /// Serializes the manifest along with a `.chunk` file for every chunk in the chunk list,
+    /// giving a self-hostable distribution. `chunk_data` maps chunk GUIDs to their decompressed data.
+    pub fn export_bundle(
+        &self,
+        chunk_data: &HashMap<shared::FGuid, Vec<u8>>,
+    ) -> ParseResult<(Vec<u8>, Vec<chunks::ChunkFile>)> {
+        let manifest = self.serialize()?;
+        let feature_level = self.meta.feature_level();
+
+        let mut chunk_files = Vec::with_capacity(self.chunk_list.chunks().len());
+        for chunk_info in self.chunk_list.chunks() {
+            let data = chunk_data
+                .get(chunk_info.guid())
+                .ok_or(crate::error::ParseError::MissingChunk(*chunk_info.guid()))?;
+
+            let chunk = chunks::FChunk::new(chunk_info, data.clone());
+            chunk_files.push((chunk_info.cloud_path(feature_level), chunk.serialize()?));
+        }
+
+        Ok((manifest, chunk_files))
+    }
+
+    /// Returns the chunk GUIDs present in both manifests with a different SHA hash, in the chunk list order of `b`.
+    /// Such a GUID names different content in each manifest, which makes `merge` fail
+    /// and means the manifests can't share a chunk store.
+    pub fn guid_content_conflicts(a: &FManifest, b: &FManifest) -> Vec<shared::FGuid> {
+        let hashes: HashMap<&shared::FGuid, &shared::FSHAHash> = a
+            .chunk_list
+            .chunks()
+            .iter()
+            .map(|chunk| (chunk.guid(), chunk.sha_hash()))
+            .collect();
+
+        b.chunk_list
+            .chunks()
+            .iter()
+            .filter(|chunk| {
+                hashes
+                    .get(chunk.guid())
+                    .is_some_and(|hash| *hash != chunk.sha_hash())
+            })
+            .map(|chunk| *chunk.guid())
+            .collect()
+    }
+
+    /// Merges two manifests, e.g. a base game and a patch.
+    ///
+    /// Files from `overlay` replace base files with the same name and the chunk list becomes
+    /// the union of both. Header and meta are taken from `overlay`, raised to the feature level
+    /// of `base` if it is newer so the merged chunk list keeps all its columns.
+    pub fn merge(base: &FManifest, overlay: &FManifest) -> ParseResult<FManifest> {
+        let chunk_list = base.chunk_list.union(&overlay.chunk_list)?;
+
+        let mut header = overlay.header.clone();
+        let mut meta = overlay.meta.clone();
+        if chunk_list._manifest_version.to_i32() > overlay.header.version().to_i32() {
+            header = header::FManifestHeader::new(
+                header.magic(),
+                0,
+                0,
+                0,
+                Default::default(),
+                header.stored_as(),
+                chunk_list._manifest_version,
+            );
+            meta.feature_level = chunk_list._manifest_version;
+        }
+
+        let mut custom_fields = base.custom_fields.clone();
+        custom_fields.fields.extend(
+            overlay
+                .custom_fields
+                .fields
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+
+        Ok(FManifest {
+            header,
+            meta,
+            chunk_list,
+            file_list: base.file_list.overlay(&overlay.file_list),
+            custom_fields,
+            data: Vec::new(),
+            unknown_trailing: Vec::new(),
+            compressed_body: None,
+        })
+    }
+
     /// Convenience method to write the serialized manifest to a file
     pub fn write_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> ParseResult<()> {
         let data = self.serialize()?;
-        std::fs::write(path, data).map_err(|_| crate::error::ParseError::InvalidData)?;
+        std::fs::write(path, data).map_err(crate::error::ParseError::Io)?;
         Ok(())
     }
+
+    /// Removes the optional MD5/SHA256 hashes and MIME types from every file to shrink the manifest.
+    ///
+    /// Section sizes are recomputed on the next call to `serialize`.
+    pub fn strip_extra_hashes(&mut self) {
+        self.file_list.strip_extra_hashes();
+    }
+
+    /// Returns every file whose name matches the glob `pattern` (`*` and `?` wildcards, path separators normalized)
+    pub fn find_files_by_glob(&self, pattern: &str) -> Vec<&file_manifest::FFileManifest> {
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|entry| crate::helper::glob_match(pattern, entry.filename()))
+            .collect()
+    }
+
+    /// Returns every file inside the directory `dir_prefix`, at any depth.
+    /// Separators are normalized, and `dir_prefix` only matches whole directory names,
+    /// so "Engine/Content" doesn't match "Engine/ContentExtra/a.pak".
+    pub fn files_under(&self, dir_prefix: &str) -> Vec<&file_manifest::FFileManifest> {
+        let dir = dir_prefix.replace('\\', "/");
+        let dir = dir.trim_end_matches('/');
+        if dir.is_empty() {
+            return self.file_list.entries().iter().collect();
+        }
+
+        let prefix = format!("{}/", dir);
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| file.normalized_filename().starts_with(&prefix))
+            .collect()
+    }
+
+    /// Returns the total size of the files inside each directory, down to `depth` levels.
+    ///
+    /// Every file counts towards all of its parent directories up to `depth`, so nested sizes
+    /// add up like in a treemap. The empty key is the root and holds the total install size.
+    pub fn directory_sizes(&self, depth: usize) -> BTreeMap<String, u64> {
+        let mut sizes = BTreeMap::new();
+
+        for file in self.file_list.entries() {
+            let filename = file.normalized_filename();
+            let size = file.file_size();
+
+            *sizes.entry(String::new()).or_insert(0) += size;
+
+            // Files at the root only count towards the total
+            let parent = match filename.rsplit_once('/') {
+                Some((parent, _)) => parent,
+                None => continue,
+            };
+
+            let mut directory = String::new();
+            for component in parent.split('/').take(depth) {
+                if !directory.is_empty() {
+                    directory.push('/');
+                }
+                directory.push_str(component);
+
+                *sizes.entry(directory.clone()).or_insert(0) += size;
+            }
+        }
+
+        sizes
+    }
+
+    /// Returns the files that are symlinks, see `FFileManifest::is_symlink`
+    pub fn symlinks(&self) -> Vec<&file_manifest::FFileManifest> {
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| file.is_symlink())
+            .collect()
+    }
+
+    /// Returns the file entry with the given name, if any
+    pub fn find_file(&self, filename: &str) -> Option<&file_manifest::FFileManifest> {
+        self.file_list
+            .entries()
+            .iter()
+            .find(|entry| entry.filename() == filename)
+    }
+
+    /// Returns where `filename` should be extracted to under `install_root`.
+    ///
+    /// Both separators are accepted and leading separators are ignored, so the file always lands
+    /// inside the root. A `..` component or a drive prefix is rejected, so a malicious manifest
+    /// can't make an extractor write outside of the install directory.
+    pub fn resolve_install_path(
+        &self,
+        filename: &str,
+        install_root: &std::path::Path,
+    ) -> ParseResult<std::path::PathBuf> {
+        let mut path = install_root.to_path_buf();
+
+        for component in filename.split(['/', '\\']) {
+            match component {
+                "" | "." => continue,
+                // Outside of the install root, or to a drive or alternate data stream
+                ".." => return Err(crate::error::ParseError::InvalidData),
+                _ if component.contains(':') => return Err(crate::error::ParseError::InvalidData),
+                _ => path.push(component),
+            }
+        }
+
+        Ok(path)
+    }
+
+    /// Returns where every file should be extracted to under `install_root`, see `resolve_install_path`.
+    ///
+    /// With `rewrite_prefix` set to `(from, to)`, files under the directory `from` are moved
+    /// under `to` first, e.g. `("FortniteGame/Content", "Content")`. Other files keep their path.
+    pub fn retarget_install_paths(
+        &self,
+        install_root: &std::path::Path,
+        rewrite_prefix: Option<(&str, &str)>,
+    ) -> ParseResult<Vec<(&file_manifest::FFileManifest, std::path::PathBuf)>> {
+        let normalize = |path: &str| path.replace('\\', "/").trim_matches('/').to_owned();
+
+        self.file_list
+            .entries()
+            .iter()
+            .map(|file| {
+                let mut filename = normalize(file.filename());
+
+                if let Some((from, to)) = rewrite_prefix {
+                    let from = normalize(from);
+                    let rest = if from.is_empty() {
+                        Some(filename.as_str())
+                    } else {
+                        filename
+                            .strip_prefix(&from)
+                            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                    };
+
+                    if let Some(rest) = rest {
+                        filename = format!("{}/{}", normalize(to), rest);
+                    }
+                }
+
+                Ok((file, self.resolve_install_path(&filename, install_root)?))
+            })
+            .collect()
+    }
+
+    /// Returns the feature level a client needs to support to install this manifest
+    pub fn requires_feature_level(&self) -> shared::EFeatureLevel {
+        self.meta.feature_level().effective()
+    }
+
+    /// Returns whether optimised delta manifests can be generated between this build and others,
+    /// which needs `FirstOptimisedDelta` (the runtime generated build id the delta files are keyed by).
+    ///
+    /// This is not the same as the manifest being a delta, see `is_delta`.
+    pub fn supports_optimised_delta(&self) -> bool {
+        self.meta.feature_level().effective().to_i32()
+            >= shared::EFeatureLevel::FirstOptimisedDelta.to_i32()
+    }
+
+    /// Returns whether this is an optimised delta manifest rather than a full one.
+    ///
+    /// The binary format has no flag for it: a delta only lists the chunks it adds, while its files
+    /// also reference chunks of the build it patches, so it is told apart by chunk parts missing
+    /// from its own chunk list. Below `FirstOptimisedDelta` those are treated as corruption instead.
+    /// The source build id isn't stored either, it is part of the path the delta was downloaded from.
+    pub fn is_delta(&self) -> bool {
+        self.supports_optimised_delta() && !self.dangling_chunk_part_refs().is_empty()
+    }
+
+    /// Returns whether a client supporting feature levels up to `client_max` can install this manifest
+    pub fn supported_by(&self, client_max: shared::EFeatureLevel) -> bool {
+        self.requires_feature_level().to_i32() <= client_max.effective().to_i32()
+    }
+
+    /// Returns the normalized filenames that appear more than once in the file list, sorted
+    pub fn duplicate_filenames(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut duplicates = BTreeSet::new();
+
+        for file in self.file_list.entries() {
+            let filename = file.normalized_filename();
+            if !seen.insert(filename.clone()) {
+                duplicates.insert(filename);
+            }
+        }
+
+        duplicates.into_iter().collect()
+    }
+
+    /// Returns the distinct MIME types of the files, sorted.
+    /// Missing and empty MIME types are both written as an empty string, so neither is included.
+    pub fn distinct_mime_types(&self) -> Vec<String> {
+        let mime_types: BTreeSet<&str> = self
+            .file_list
+            .entries()
+            .iter()
+            .filter_map(|file| file.mime_type())
+            .filter(|mime_type| !mime_type.is_empty())
+            .collect();
+
+        mime_types.into_iter().map(str::to_owned).collect()
+    }
+
+    /// Returns the files that reference none of the chunks of `filename`, so they can be downloaded
+    /// in parallel with it without fetching the same chunks. Empty if `filename` isn't in the manifest.
+    pub fn files_independent_of(&self, filename: &str) -> Vec<&file_manifest::FFileManifest> {
+        let target = match self.find_file(filename) {
+            Some(target) => target,
+            None => return vec![],
+        };
+
+        let target_chunks: HashSet<&shared::FGuid> = target
+            .chunk_parts()
+            .iter()
+            .map(|part| part.guid())
+            .collect();
+
+        self.file_list
+            .entries()
+            .iter()
+            .filter(|file| !std::ptr::eq(*file, target))
+            .filter(|file| {
+                file.chunk_parts()
+                    .iter()
+                    .all(|part| !target_chunks.contains(part.guid()))
+            })
+            .collect()
+    }
+
+    /// Returns how many files reference each chunk, a file using a chunk several times counting once.
+    /// Chunks no file references are absent.
+    pub fn chunk_reference_counts(&self) -> HashMap<&shared::FGuid, usize> {
+        let mut counts = HashMap::new();
+
+        for file in self.file_list.entries() {
+            let file_chunks: HashSet<&shared::FGuid> =
+                file.chunk_parts().iter().map(|part| part.guid()).collect();
+
+            for guid in file_chunks {
+                *counts.entry(guid).or_insert(0) += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Returns the chunks referenced by `filename` and no other file, in chunk list order.
+    /// These can be garbage collected once the file is removed or updated.
+    /// Empty if `filename` isn't in the manifest.
+    pub fn exclusive_chunks_of(&self, filename: &str) -> Vec<&chunk_info::FChunkInfo> {
+        let target = match self.find_file(filename) {
+            Some(target) => target,
+            None => return vec![],
+        };
+
+        let counts = self.chunk_reference_counts();
+        let exclusive: HashSet<&shared::FGuid> = target
+            .chunk_parts()
+            .iter()
+            .map(|part| part.guid())
+            .filter(|guid| counts.get(guid) == Some(&1))
+            .collect();
+
+        self.chunk_list
+            .chunks()
+            .iter()
+            .filter(|chunk| exclusive.contains(chunk.guid()))
+            .collect()
+    }
+
+    /// Returns the chunk plan needed to download and assemble `filename`
+    pub fn file_chunk_plan(&self, filename: &str) -> Option<plan::FileChunkPlan> {
+        self.find_file(filename)
+            .map(|file| plan::FileChunkPlan::new(self, file))
+    }
+
+    /// Returns the chunks to fetch to complete an install of the files selected by `tags`,
+    /// skipping the chunks `have` reports as already present. See `plan::DownloadPlan::new`
+    pub fn download_plan(
+        &self,
+        have: impl Fn(&shared::FGuid) -> bool,
+        tags: &[String],
+    ) -> plan::DownloadPlan {
+        plan::DownloadPlan::new(self, have, tags)
+    }
+
+    /// Returns every referenced chunk, ordered so files complete as early as possible.
+    /// Installers assembling files as chunks arrive then hold fewer partial files. See `plan::chunk_order`
+    pub fn suggest_chunk_order(&self) -> Vec<shared::FGuid> {
+        plan::chunk_order(self)
+    }
+
+    /// Lists which chunks `filename` started and stopped referencing between the `old` and `new` builds
+    pub fn file_chunk_diff(
+        old: &FManifest,
+        new: &FManifest,
+        filename: &str,
+    ) -> diff::FileChunkDiff {
+        diff::FileChunkDiff::new(filename, old.find_file(filename), new.find_file(filename))
+    }
+
+    /// Returns the original compressed body if the manifest was parsed with `parse_keep_compressed`
+    pub fn compressed_body(&self) -> Option<&[u8]> {
+        self.compressed_body.as_deref()
+    }
+
+    /// Returns the declared size, version and entry count of every section
+    pub fn section_report(&self) -> summary::SectionReport {
+        summary::SectionReport::new(self)
+    }
+
+    /// Rewrites every filename in place to use forward slashes only.
+    ///
+    /// Section sizes are recomputed on the next call to `serialize`.
+    pub fn normalize_all_paths(&mut self) {
+        self.file_list.normalize_paths();
+    }
+
+    /// Returns a short overview of the manifest, including chunk fragmentation metrics
+    pub fn summary(&self) -> summary::ManifestSummary {
+        summary::ManifestSummary::new(self)
+    }
+
+    /// Returns how to start the app and its prerequisites, see `summary::LaunchInfo`
+    pub fn launch_info(&self) -> summary::LaunchInfo {
+        summary::LaunchInfo::new(&self.meta)
+    }
+
+    /// Returns the feature level along with the version of every section, see `summary::SectionVersions`
+    pub fn section_versions(&self) -> summary::SectionVersions {
+        summary::SectionVersions::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_json_version_is_parsed_as_stores_chunk_file_sizes() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        fixtures::set_feature_level(&mut manifest, shared::EFeatureLevel::BrokenJsonVersion);
+
+        let (parsed, warnings) = parse_with_warnings(&manifest.serialize().unwrap()).unwrap();
+
+        assert!(warnings.contains(&ParseWarning::BrokenJsonVersion));
+        assert_eq!(
+            parsed.header.version(),
+            shared::EFeatureLevel::BrokenJsonVersion
+        );
+        assert_eq!(
+            parsed.header.version().effective(),
+            shared::EFeatureLevel::StoresChunkFileSizes
+        );
+        // Level 8 stores the chunk sizes but not their SHA1 hashes
+        let chunk = &parsed.chunk_list.chunks()[0];
+        assert_eq!(chunk.uncompressed_size(), fixtures::CHUNK_SIZE);
+        assert_eq!(*chunk.sha_hash(), shared::FSHAHash::default());
+        assert_eq!(parsed.file_list.entries()[0].file_size(), 8);
+    }
+
+    #[test]
+    fn find_files_by_glob_matches_filenames() {
+        let manifest = fixtures::manifest(&[
+            ("Game/Content/Paks/a.pak", b"a"),
+            ("Game\\Content\\Paks\\b.pak", b"b"),
+            ("Game/Binaries/Game.exe", b"c"),
+        ]);
+
+        let names = |pattern| {
+            manifest
+                .find_files_by_glob(pattern)
+                .iter()
+                .map(|file| file.filename())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names("*.pak"),
+            ["Game/Content/Paks/a.pak", "Game\\Content\\Paks\\b.pak"]
+        );
+        assert_eq!(names("Game/Content/Paks/?.pak").len(), 2);
+        assert_eq!(names("Game/Binaries/*"), ["Game/Binaries/Game.exe"]);
+    }
+
+    #[test]
+    fn content_hash_does_not_depend_on_storage() {
+        let compressed = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")]);
+        let mut uncompressed = compressed.clone();
+        fixtures::set_stored_as(&mut uncompressed, shared::EManifestStorageFlags::None);
+
+        let compressed = parse(&compressed.serialize().unwrap()).unwrap();
+        let uncompressed = parse(&uncompressed.serialize().unwrap()).unwrap();
+
+        assert_ne!(compressed.header.sha_hash(), &shared::FSHAHash::default());
+        assert_ne!(compressed.data, uncompressed.data);
+        assert_eq!(compressed.content_hash(), uncompressed.content_hash());
+    }
+
+    #[test]
+    fn content_hash_does_not_depend_on_order() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")]);
+        let mut reordered = manifest.clone();
+        reordered.chunk_list.chunks.reverse();
+        reordered.file_list.entries.reverse();
+
+        assert_eq!(manifest.content_hash(), reordered.content_hash());
+
+        reordered.file_list.entries[0].hash = shared::FSHAHash::default();
+        assert_ne!(manifest.content_hash(), reordered.content_hash());
+    }
+
+    #[test]
+    fn export_bundle_produces_a_parsable_manifest_and_chunks() {
+        let (manifest, chunk_data) =
+            fixtures::manifest_with_data(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")]);
+
+        let (manifest_bytes, chunk_files) = manifest.export_bundle(&chunk_data).unwrap();
+
+        let parsed = parse(&manifest_bytes).unwrap();
+        assert_eq!(parsed.file_list.entries().len(), 2);
+        assert_eq!(chunk_files.len(), manifest.chunk_list.chunks().len());
+
+        let chunk_info = &parsed.chunk_list.chunks()[0];
+        let (path, chunk_file) = &chunk_files[0];
+        assert_eq!(*path, chunk_info.cloud_path(parsed.meta.feature_level()));
+        let chunk = chunks::FChunk::parse_verified(chunk_file, chunk_info.guid()).unwrap();
+        assert_eq!(chunk.data(), chunk_data[chunk_info.guid()]);
+    }
+
+    #[test]
+    fn export_bundle_fails_without_the_chunk_data() {
+        let (manifest, mut chunk_data) = fixtures::manifest_with_data(&[("a.bin", b"abcdefgh")]);
+        let missing = *manifest.chunk_list.chunks()[1].guid();
+        chunk_data.remove(&missing);
+
+        assert!(matches!(
+            manifest.export_bundle(&chunk_data),
+            Err(crate::error::ParseError::MissingChunk(guid)) if guid == missing
+        ));
+    }
+
+    fn strict_versions() -> ParseOptions {
+        ParseOptions {
+            strict_versions: true,
+            ..Default::default()
+        }
+    }
+
+    fn has_inconsistent_versions(warnings: &[ParseWarning]) -> bool {
+        warnings
+            .iter()
+            .any(|warning| matches!(warning, ParseWarning::InconsistentVersions(_)))
+    }
+
+    #[test]
+    fn consistent_versions_are_not_reported() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+
+        let (_, warnings) = parse_with_warnings(&bytes).unwrap();
+        assert!(!has_inconsistent_versions(&warnings));
+        assert!(FManifestParser::with_options(&bytes, strict_versions())
+            .parse()
+            .is_ok());
+    }
+
+    #[test]
+    fn header_and_meta_feature_levels_must_agree() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        manifest.meta.feature_level = shared::EFeatureLevel::VariableSizeChunks;
+        let bytes = manifest.serialize().unwrap();
+
+        let (_, warnings) = parse_with_warnings(&bytes).unwrap();
+        assert!(has_inconsistent_versions(&warnings));
+        assert!(matches!(
+            FManifestParser::with_options(&bytes, strict_versions()).parse(),
+            Err(crate::error::ParseError::InconsistentVersions(_))
+        ));
+    }
+
+    #[test]
+    fn build_id_levels_need_meta_data_version_1() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+
+        let mut writer = crate::writer::ByteWriter::new();
+        manifest.meta.write(&mut writer, 0);
+        manifest.chunk_list.write(&mut writer);
+        manifest.file_list.write(&mut writer);
+        manifest.custom_fields.write(&mut writer);
+        let body = writer.into_bytes();
+        let hash = shared::FSHAHash::new_from_hashable(&body);
+        let bytes = manifest.serialize_with_body(body, hash).unwrap();
+
+        let (parsed, warnings) = parse_with_warnings(&bytes).unwrap();
+        assert_eq!(parsed.meta.data_version(), 0);
+        assert!(has_inconsistent_versions(&warnings));
+        assert!(FManifestParser::with_options(&bytes, strict_versions())
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn fixed_size_levels_reject_variable_size_chunks() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        fixtures::set_feature_level(&mut manifest, shared::EFeatureLevel::StoresChunkFileSizes);
+        let bytes = manifest.serialize().unwrap();
+        let (_, warnings) = parse_with_warnings(&bytes).unwrap();
+        assert!(!has_inconsistent_versions(&warnings));
+
+        manifest.chunk_list.chunks[0].uncompressed_size = 2 * 1024 * 1024;
+        let bytes = manifest.serialize().unwrap();
+
+        let (_, warnings) = parse_with_warnings(&bytes).unwrap();
+        assert!(has_inconsistent_versions(&warnings));
+        assert!(FManifestParser::with_options(&bytes, strict_versions())
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn merge_replaces_files_and_unions_chunks() {
+        let base = fixtures::manifest(&[("a.bin", b"aaaabbbb"), ("b.bin", b"cccc")]);
+        let overlay = fixtures::manifest(&[("b.bin", b"dddd"), ("c.bin", b"aaaa")]);
+
+        let merged = FManifest::merge(&base, &overlay).unwrap();
+
+        assert_eq!(merged.file_list.entries().len(), 3);
+        assert_eq!(
+            merged.find_file("b.bin").unwrap().hash(),
+            overlay.find_file("b.bin").unwrap().hash()
+        );
+        // "aaaa" is shared by both manifests and only listed once
+        assert_eq!(merged.chunk_list.chunks().len(), 4);
+
+        let reparsed = fixtures::reparsed(&merged);
+        assert_eq!(reparsed.file_list.entries().len(), 3);
+        assert_eq!(reparsed.chunk_list.chunks().len(), 4);
+    }
+
+    #[test]
+    fn merge_fails_on_conflicting_chunks() {
+        let base = fixtures::manifest(&[("a.bin", b"aaaa")]);
+        let mut overlay = base.clone();
+        let guid = *overlay.chunk_list.chunks()[0].guid();
+        overlay.chunk_list.chunks[0].sha_hash = shared::FSHAHash::new([0xff; 20]);
+
+        match FManifest::merge(&base, &overlay) {
+            Err(crate::error::ParseError::ChunkConflict(conflict)) => assert_eq!(conflict, guid),
+            other => panic!("expected a chunk conflict, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn kept_compressed_body_decompresses_to_the_body() {
+        use std::io::Read;
+
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let bytes = manifest.serialize().unwrap();
+        let parsed = FManifestParser::new(&bytes)
+            .parse_keep_compressed()
+            .unwrap();
+
+        let mut body = Vec::new();
+        flate2::read::ZlibDecoder::new(parsed.compressed_body().unwrap())
+            .read_to_end(&mut body)
+            .unwrap();
+
+        let mut uncompressed = parsed.clone();
+        fixtures::set_stored_as(&mut uncompressed, shared::EManifestStorageFlags::None);
+        let uncompressed_bytes = uncompressed.serialize().unwrap();
+        let header_size = parsed.header.header_size() as usize;
+        assert_eq!(body, uncompressed_bytes[header_size..]);
+
+        assert!(parse(&bytes).unwrap().compressed_body().is_none());
+    }
+
+    #[test]
+    fn normalize_all_paths_rewrites_windows_paths() {
+        let mut manifest = fixtures::manifest(&[
+            ("Game\\Binaries\\Win64\\Game.exe", b"abcd"),
+            ("Game/Content/a.pak", b"efgh"),
+        ]);
+        let entry = &manifest.file_list.entries()[0];
+        assert_eq!(entry.normalized_filename(), "Game/Binaries/Win64/Game.exe");
+
+        manifest.normalize_all_paths();
+
+        let reparsed = fixtures::reparsed(&manifest);
+        let filenames = reparsed
+            .file_list
+            .entries()
+            .iter()
+            .map(|entry| entry.filename())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            ["Game/Binaries/Win64/Game.exe", "Game/Content/a.pak"]
+        );
+    }
+
+    #[test]
+    fn write_to_file_reports_io_errors() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcd")]);
+        let path = std::env::temp_dir()
+            .join("epic_manifest_wasm_missing_dir")
+            .join("manifest.bin");
+
+        match manifest.write_to_file(&path) {
+            Err(crate::error::ParseError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound)
+            }
+            other => panic!("expected an I/O error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn install_fingerprint_only_depends_on_the_files() {
+        let files: &[(&str, &[u8])] = &[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")];
+        let manifest = fixtures::manifest(files);
+        let fingerprint = manifest.install_fingerprint();
+
+        assert_eq!(
+            fixtures::reparsed(&manifest).install_fingerprint(),
+            fingerprint
+        );
+
+        let mut builder = builder::ManifestBuilder::new("TestApp", "1.0.0");
+        for (filename, data) in files.iter().rev() {
+            let strategy = builder::ChunkingStrategy::FixedSize(2);
+            builder.add_file(filename, data, strategy).unwrap();
+        }
+        assert_eq!(builder.build().0.install_fingerprint(), fingerprint);
+
+        let modified = fixtures::manifest(&[("a.bin", b"abcdefgX"), ("b.bin", b"ijkl")]);
+        assert_ne!(modified.install_fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn patch_sizes_shrink_for_newer_builds() {
+        let build = |version: &str, data: &[u8]| {
+            let mut builder = builder::ManifestBuilder::new("TestApp", version);
+            let strategy = builder::ChunkingStrategy::FixedSize(fixtures::CHUNK_SIZE);
+            builder.add_file("a.bin", data, strategy).unwrap();
+            builder.build().0
+        };
+        let target = build("4.0.0", b"aaaabbbbcccc");
+        let sources = [
+            build("1.0.0", b"111122223333"),
+            build("2.0.0", b"aaaa22223333"),
+            build("3.0.0", b"aaaabbbb3333"),
+        ];
+
+        let patch_sizes = target.patch_sizes_from(&sources);
+
+        let size_from = |source: &FManifest| patch_sizes[source.meta.build_id().unwrap()];
+        assert_eq!(patch_sizes.len(), 3);
+        assert!(size_from(&sources[0]) > size_from(&sources[1]));
+        assert!(size_from(&sources[1]) > size_from(&sources[2]));
+
+        let last_chunk = &target.chunk_list.chunks()[2];
+        assert_eq!(target.new_chunks(&sources[2]), [last_chunk]);
+        assert_eq!(size_from(&sources[2]), last_chunk.compressed_size() as u64);
+    }
+
+    #[test]
+    fn empty_manifests_round_trip() {
+        let manifest = fixtures::manifest(&[]);
+        let bytes = manifest.serialize().unwrap();
+
+        let (parsed, warnings) = parse_with_warnings(&bytes).unwrap();
+
+        assert!(warnings.is_empty());
+        assert!(parsed.file_list.entries().is_empty());
+        assert!(parsed.chunk_list.chunks().is_empty());
+        assert!(parsed.custom_fields.fields.is_empty());
+        assert_eq!(parsed.serialize().unwrap(), bytes);
+    }
+
+    #[test]
+    fn lenient_sizes_skip_unknown_bytes_at_the_end_of_a_section() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+
+        // Meta section with 4 unknown bytes at its end, covered by its size
+        let mut writer = crate::writer::ByteWriter::new();
+        manifest
+            .meta
+            .write(&mut writer, manifest.meta.data_version());
+        writer.write_bytes(&[1, 2, 3, 4]);
+        writer.write_u32_at(0, writer.tell() as u32);
+        manifest.chunk_list.write(&mut writer);
+        manifest.file_list.write(&mut writer);
+        manifest.custom_fields.write(&mut writer);
+        let body = writer.into_bytes();
+        let hash = shared::FSHAHash::new_from_hashable(&body);
+        let bytes = manifest.serialize_with_body(body, hash).unwrap();
+
+        assert!(parse(&bytes).is_err());
+
+        let options = ParseOptions {
+            lenient_sizes: true,
+            ..Default::default()
+        };
+        let (parsed, warnings) = FManifestParser::with_options(&bytes, options)
+            .parse_with_warnings()
+            .unwrap();
+        assert!(matches!(
+            warnings[0],
+            ParseWarning::SectionSizeMismatch {
+                section: "Meta",
+                ..
+            }
+        ));
+        assert_eq!(parsed.meta.app_name(), "TestApp");
+        assert_eq!(parsed.file_list.entries()[0].filename(), "a.bin");
+    }
+
+    #[test]
+    fn distinct_mime_types_are_deduplicated_and_sorted() {
+        let mut manifest = fixtures::manifest(&[
+            ("index.html", b"a"),
+            ("style.css", b"b"),
+            ("about.html", b"c"),
+            ("data.bin", b"d"),
+            ("readme", b"e"),
+        ]);
+        let mime_types = [
+            Some("text/html"),
+            Some("text/css"),
+            Some("text/html"),
+            Some(""),
+            None,
+        ];
+        for (entry, mime_type) in manifest.file_list.entries.iter_mut().zip(mime_types) {
+            entry.mime_type = mime_type.map(str::to_owned);
+        }
+
+        assert_eq!(manifest.distinct_mime_types(), ["text/css", "text/html"]);
+    }
+
+    #[test]
+    fn duplicate_filenames_are_reported_after_normalization() {
+        let manifest = fixtures::manifest(&[
+            ("Game/a.bin", b"a"),
+            ("Game\\a.bin", b"b"),
+            ("Game/b.bin", b"c"),
+        ]);
+
+        assert_eq!(manifest.duplicate_filenames(), ["Game/a.bin"]);
+
+        let (_, warnings) = parse_with_warnings(&manifest.serialize().unwrap()).unwrap();
+        let duplicates = vec!["Game/a.bin".to_owned()];
+        assert!(warnings.contains(&ParseWarning::DuplicateFilenames(duplicates)));
+    }
+
+    #[test]
+    fn supported_by_compares_against_the_client_level() {
+        use shared::EFeatureLevel;
+
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcd")]);
+        fixtures::set_feature_level(&mut manifest, EFeatureLevel::StoresChunkDataShaHashes);
+
+        assert_eq!(
+            manifest.requires_feature_level(),
+            EFeatureLevel::StoresChunkDataShaHashes
+        );
+        assert!(manifest.supported_by(EFeatureLevel::StoresPrerequisiteIds));
+        assert!(manifest.supported_by(EFeatureLevel::StoresChunkDataShaHashes));
+        assert!(!manifest.supported_by(EFeatureLevel::Unused1));
+    }
+
+    #[test]
+    fn facade_functions_round_trip() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+
+        let manifest = parse(&bytes).unwrap();
+        let json = to_json(&manifest).unwrap();
+        let restored = from_json(&json).unwrap();
+
+        assert_eq!(restored.file_list.entries()[0].filename(), "a.bin");
+        assert_eq!(create(&json).unwrap(), bytes);
+        assert!(matches!(create("{}"), Err(crate::error::Error::Json(_))));
+        assert!(parse(&bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn files_independent_of_leaves_out_files_sharing_chunks() {
+        // Identical 4 byte blocks share a chunk
+        let manifest = fixtures::manifest(&[
+            ("a.bin", b"aaaabbbb"),
+            ("b.bin", b"bbbbcccc"),
+            ("c.bin", b"cccc"),
+            ("d.bin", b"dddd"),
+        ]);
+
+        let filenames = |filename| {
+            let files = manifest.files_independent_of(filename);
+            files.iter().map(|file| file.filename()).collect::<Vec<_>>()
+        };
+        assert_eq!(filenames("a.bin"), ["c.bin", "d.bin"]);
+        assert_eq!(filenames("b.bin"), ["d.bin"]);
+        assert!(filenames("missing.bin").is_empty());
+    }
+
+    #[test]
+    fn all_required_chunks_excludes_orphans() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"aaaabbbb"), ("b.bin", b"cccc")]);
+        // The chunk of b.bin stays in the chunk list
+        manifest.file_list.entries.pop();
+        manifest.file_list._count = 1;
+
+        let required = manifest.all_required_chunks();
+
+        let chunks = manifest.chunk_list.chunks();
+        assert_eq!(required, [&chunks[0], &chunks[1]]);
+        let size = (chunks[0].compressed_size() + chunks[1].compressed_size()) as u64;
+        assert_eq!(manifest.full_install_size(), size);
+
+        let (_, warnings) = parse_with_warnings(&manifest.serialize().unwrap()).unwrap();
+        assert!(warnings.contains(&ParseWarning::OrphanChunks(1)));
+    }
+
+    #[test]
+    fn manifests_are_parsed_at_an_offset_in_a_container() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+        let mut container = vec![0xAA; 37];
+        container.extend_from_slice(&bytes);
+        container.extend_from_slice(&[0xBB; 11]);
+
+        let parsed = FManifestParser::new_at_offset(&container, 37)
+            .parse()
+            .unwrap();
+
+        assert_eq!(parsed.file_list.entries()[0].filename(), "a.bin");
+        assert_eq!(parsed.data, bytes);
+        assert!(matches!(
+            parse(&container),
+            Err(crate::error::ParseError::InvalidMagic)
+        ));
+    }
+
+    #[test]
+    fn largest_chunks_are_sorted_by_compressed_size() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"aaaabbbbccccddddeeee")]);
+        for (chunk, size) in manifest
+            .chunk_list
+            .chunks
+            .iter_mut()
+            .zip([5, 30, 10, 20, 1])
+        {
+            chunk.set_sizes(fixtures::CHUNK_SIZE, size);
+        }
+
+        let sizes = |n| {
+            let chunks = manifest.largest_chunks(n);
+            chunks
+                .iter()
+                .map(|chunk| chunk.compressed_size())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(3), [30, 20, 10]);
+        assert_eq!(sizes(10), [30, 20, 10, 5, 1]);
+        assert!(sizes(0).is_empty());
+    }
+
+    #[test]
+    fn zero_file_hashes_are_reported_as_warnings() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcd"), ("b.bin", b"efgh")]);
+        manifest.file_list.entries[1].hash = shared::FSHAHash::default();
+
+        let (_, warnings) = parse_with_warnings(&manifest.serialize().unwrap()).unwrap();
+
+        assert_eq!(warnings, [ParseWarning::ZeroFileHash("b.bin".to_owned())]);
+    }
+
+    #[test]
+    fn canonical_serialization_has_a_stable_chunk_order() {
+        let manifest = fixtures::manifest(&[("a.bin", b"aaaabbbbccccdddd")]);
+        let mut rebuilt = manifest.clone();
+        rebuilt.chunk_list.chunks.reverse();
+        assert_ne!(manifest.serialize().unwrap(), rebuilt.serialize().unwrap());
+
+        let bytes = manifest.serialize_canonical().unwrap();
+
+        assert_eq!(rebuilt.serialize_canonical().unwrap(), bytes);
+        let guids = parse(&bytes)
+            .unwrap()
+            .chunk_list
+            .chunks()
+            .iter()
+            .map(|chunk| *chunk.guid())
+            .collect::<Vec<_>>();
+        assert!(guids.is_sorted());
+    }
+
+    #[test]
+    fn parse_meta_only_returns_the_same_meta_as_a_full_parse() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+
+        let (header, meta) = FManifestParser::new(&bytes).parse_meta_only().unwrap();
+        let full = FManifestParser::new(&bytes).parse().unwrap();
+
+        assert_eq!(header.header_size(), full.header.header_size());
+        assert_eq!(
+            serde_json::to_value(&meta).unwrap(),
+            serde_json::to_value(&full.meta).unwrap()
+        );
+    }
+
+    #[test]
+    fn chunks_missing_sha_at_a_level_storing_them() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let missing = *manifest.chunk_list.chunks()[1].guid();
+        let chunk = manifest.chunk_list.get_mut(1).unwrap();
+        chunk.set_hashes(chunk.hash(), shared::FSHAHash::default());
+
+        let found = manifest.chunks_missing_sha();
+        assert_eq!(found.len(), 1);
+        assert_eq!(*found[0].guid(), missing);
+
+        fixtures::set_feature_level(&mut manifest, shared::EFeatureLevel::StoresChunkFileSizes);
+        assert!(manifest.chunks_missing_sha().is_empty());
+    }
+
+    #[test]
+    fn dangling_chunk_part_refs_lists_parts_of_removed_chunks() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"efgh")]);
+        assert!(manifest.dangling_chunk_part_refs().is_empty());
+
+        // "efgh" is shared by both files
+        let removed = manifest.chunk_list.chunks.remove(1);
+
+        assert_eq!(
+            manifest.dangling_chunk_part_refs(),
+            vec![("a.bin", removed.guid()), ("b.bin", removed.guid())]
+        );
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn json_schema_lists_the_top_level_sections() {
+        let schema: serde_json::Value = serde_json::from_str(&json_schema().unwrap()).unwrap();
+
+        let properties = schema["properties"].as_object().unwrap();
+        for section in ["header", "meta", "chunk_list", "file_list", "custom_fields"] {
+            assert!(properties.contains_key(section), "missing {}", section);
+        }
+    }
+
+    #[test]
+    fn file_changes_between_two_builds() {
+        let old = fixtures::manifest(&[
+            ("changed.bin", b"abcd"),
+            ("same.bin", b"efgh"),
+            ("removed.bin", b"ijkl"),
+        ]);
+        let new = fixtures::manifest(&[
+            ("changed.bin", b"abce"),
+            ("same.bin", b"efgh"),
+            ("added.bin", b"mnop"),
+        ]);
+
+        let names = |files: Vec<&file_manifest::FFileManifest>| {
+            files
+                .iter()
+                .map(|file| file.filename().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(new.files_changed_since(&old)), ["changed.bin"]);
+        assert_eq!(names(new.files_added_since(&old)), ["added.bin"]);
+        assert_eq!(names(new.files_removed_since(&old)), ["removed.bin"]);
+    }
+
+    #[test]
+    fn serialized_size_matches_the_uncompressed_output() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("dé.bin", b"ij")]);
+        fixtures::set_stored_as(&mut manifest, shared::EManifestStorageFlags::None);
+        manifest.file_list.entries[0].install_tags = vec!["hd".to_string()];
+        manifest
+            .custom_fields
+            .fields
+            .insert("BuildLabel".to_string(), "Live".to_string());
+
+        assert_eq!(
+            manifest.serialized_size(),
+            manifest.serialize().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn files_under_matches_whole_directory_names() {
+        let manifest = fixtures::manifest(&[
+            ("Engine\\Content\\a.pak", b"ab"),
+            ("Engine/Content/Maps/b.umap", b"cd"),
+            ("Engine/ContentExtra/c.pak", b"ef"),
+            ("Game.exe", b"gh"),
+        ]);
+
+        let names = |dir| {
+            manifest
+                .files_under(dir)
+                .iter()
+                .map(|file| file.filename().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("Engine/Content/"),
+            ["Engine\\Content\\a.pak", "Engine/Content/Maps/b.umap"]
+        );
+        assert_eq!(
+            names("Engine\\Content\\Maps"),
+            ["Engine/Content/Maps/b.umap"]
+        );
+        assert_eq!(names("").len(), 4);
+        assert!(names("Engine/Cont").is_empty());
+    }
+
+    #[test]
+    fn directory_sizes_add_up_nested_directories() {
+        let manifest = fixtures::manifest(&[
+            ("Engine/Content/a.pak", b"abcd"),
+            ("Engine/Content/Maps/b.umap", b"efghij"),
+            ("Engine/Binaries/c.dll", b"kl"),
+            ("Game.exe", b"m"),
+        ]);
+
+        let sizes = manifest.directory_sizes(2);
+
+        assert_eq!(
+            sizes,
+            BTreeMap::from([
+                (String::new(), 13),
+                ("Engine".to_string(), 12),
+                ("Engine/Binaries".to_string(), 2),
+                ("Engine/Content".to_string(), 10),
+            ])
+        );
+        assert_eq!(manifest.directory_sizes(0).len(), 1);
+    }
+
+    #[test]
+    fn delta_manifests_reference_chunks_they_dont_list() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        fixtures::set_feature_level(&mut manifest, shared::EFeatureLevel::FirstOptimisedDelta);
+        assert!(manifest.supports_optimised_delta());
+        assert!(!manifest.is_delta());
+
+        // The first chunk comes from the build being patched
+        manifest.chunk_list.chunks.remove(0);
+        assert!(manifest.is_delta());
+
+        fixtures::set_feature_level(&mut manifest, shared::EFeatureLevel::StoresPrerequisiteIds);
+        assert!(!manifest.supports_optimised_delta());
+        assert!(!manifest.is_delta());
+    }
+
+    #[test]
+    fn incremental_body_hash_matches_a_one_shot_hash() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ij")]);
+        manifest.unknown_trailing = vec![1, 2, 3];
+
+        let (body, hash) = manifest.serialize_body_with_hash();
+
+        assert_eq!(hash, shared::FSHAHash::new_from_hashable(&body[..]));
+        assert_eq!(body, manifest.serialize_body());
+        let parsed = FManifestParser::new(&manifest.serialize().unwrap())
+            .parse()
+            .unwrap();
+        assert_eq!(*parsed.header.sha_hash(), hash);
+    }
+
+    #[test]
+    fn sha1sums_use_the_coreutils_format() {
+        let manifest = fixtures::manifest(&[("a.txt", b"abc"), ("Sub\\b.txt", b"")]);
+
+        assert_eq!(
+            manifest.sha1sums(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d  a.txt\n\
+             da39a3ee5e6b4b0d3255bfef95601890afd80709  Sub/b.txt\n"
+        );
+    }
+
+    #[test]
+    fn semantically_eq_ignores_storage_and_order_but_not_versions() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ij")]);
+
+        let mut other = manifest.clone();
+        fixtures::set_stored_as(&mut other, shared::EManifestStorageFlags::None);
+        other.chunk_list.chunks.reverse();
+        other.file_list.entries.reverse();
+        assert!(manifest.semantically_eq(&other));
+
+        other.file_list._version = 1;
+        assert!(!manifest.semantically_eq(&other));
+    }
+
+    #[test]
+    fn previously_asymmetric_manifests_roundtrip() {
+        let files: &[(&str, &[u8])] = &[("a.bin", b"abcdefgh"), ("dé€.bin", b"ij")];
+        let mut extra_hashes = fixtures::manifest(files);
+        fixtures::add_extra_hashes(&mut extra_hashes);
+        let mut mime_only = fixtures::manifest(files);
+        mime_only.file_list._version = 1;
+        mime_only.file_list.entries[0].mime_type = Some("text/plain".to_owned());
+        let mut old_level = fixtures::manifest(files);
+        fixtures::set_feature_level(&mut old_level, shared::EFeatureLevel::StoresChunkFileSizes);
+
+        for manifest in [extra_hashes, mime_only, old_level] {
+            manifest.check_roundtrip().unwrap();
+        }
+
+        // An interior nul can be written but not read back
+        let nul_filename = fixtures::manifest(&[("a\0b.bin", b"ab")]);
+        assert!(nul_filename.check_roundtrip().is_err());
+    }
+
+    #[test]
+    fn install_paths_stay_inside_the_install_root() {
+        let manifest = fixtures::manifest(&[]);
+        let root = std::path::Path::new("install");
+
+        assert_eq!(
+            manifest
+                .resolve_install_path("Engine\\Content/./a.pak", root)
+                .unwrap(),
+            root.join("Engine").join("Content").join("a.pak")
+        );
+        assert_eq!(
+            manifest.resolve_install_path("/a.bin", root).unwrap(),
+            root.join("a.bin")
+        );
+        for filename in [
+            "../a.bin",
+            "Engine/../../a.bin",
+            "C:\\a.bin",
+            "a.bin:stream",
+        ] {
+            assert!(
+                matches!(
+                    manifest.resolve_install_path(filename, root),
+                    Err(crate::error::ParseError::InvalidData)
+                ),
+                "{} was accepted",
+                filename
+            );
+        }
+    }
+
+    #[test]
+    fn retarget_install_paths_rewrites_a_directory_prefix() {
+        let manifest = fixtures::manifest(&[
+            ("Game/Content/a.pak", b"abcd"),
+            ("Game/ContentExtra/b.pak", b"efgh"),
+            ("Game/Binaries/Game.exe", b"ijkl"),
+        ]);
+        let root = std::path::Path::new("install");
+
+        let paths = manifest
+            .retarget_install_paths(root, Some(("Game\\Content/", "Data")))
+            .unwrap()
+            .into_iter()
+            .map(|(file, path)| (file.filename().to_owned(), path))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "Game/Content/a.pak".to_owned(),
+                    root.join("Data").join("a.pak")
+                ),
+                (
+                    "Game/ContentExtra/b.pak".to_owned(),
+                    root.join("Game").join("ContentExtra").join("b.pak")
+                ),
+                (
+                    "Game/Binaries/Game.exe".to_owned(),
+                    root.join("Game").join("Binaries").join("Game.exe")
+                ),
+            ]
+        );
+
+        // The rewritten paths are checked for traversal too
+        assert!(matches!(
+            manifest.retarget_install_paths(root, Some(("Game", ".."))),
+            Err(crate::error::ParseError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn exclusive_chunks_exclude_shared_ones() {
+        // "abcd" is used twice by a.bin but by no other file, "efgh" is shared with b.bin
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdabcdefgh"), ("b.bin", b"efghijkl")]);
+        let guid_of = |data: &[u8]| {
+            *manifest
+                .chunk_list
+                .chunks()
+                .iter()
+                .find(|chunk| *chunk.sha_hash() == shared::FSHAHash::new_from_hashable(data))
+                .unwrap()
+                .guid()
+        };
+
+        let counts = manifest.chunk_reference_counts();
+        assert_eq!(counts[&guid_of(b"abcd")], 1);
+        assert_eq!(counts[&guid_of(b"efgh")], 2);
+
+        let exclusive = |filename| {
+            manifest
+                .exclusive_chunks_of(filename)
+                .iter()
+                .map(|chunk| *chunk.guid())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(exclusive("a.bin"), [guid_of(b"abcd")]);
+        assert_eq!(exclusive("b.bin"), [guid_of(b"ijkl")]);
+        assert!(exclusive("missing.bin").is_empty());
+    }
+
+    #[test]
+    fn guid_content_conflicts_lists_guids_with_different_hashes() {
+        let a = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let mut b = a.clone();
+        assert!(FManifest::guid_content_conflicts(&a, &b).is_empty());
+
+        let chunk = b.chunk_list.get_mut(1).unwrap();
+        chunk.set_hashes(chunk.hash(), shared::FSHAHash::new_from_hashable(b"ijkl"));
+        let conflicting = *chunk.guid();
+
+        assert_eq!(FManifest::guid_content_conflicts(&a, &b), [conflicting]);
+        assert_eq!(FManifest::guid_content_conflicts(&b, &a), [conflicting]);
+    }
+
+    #[test]
+    fn group_histogram_sums_to_the_chunk_count() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefghijklmnop"), ("b.bin", b"qr")]);
+
+        let histogram = manifest.group_histogram();
+
+        assert_eq!(
+            histogram.iter().sum::<u32>() as usize,
+            manifest.chunk_list.chunks().len()
+        );
+        for chunk in manifest.chunk_list.chunks() {
+            assert!(histogram[chunk.group_num() as usize] > 0);
+        }
+    }
+
+    #[test]
+    fn file_size_mismatches_lists_files_claiming_another_size() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ij")]);
+        assert!(manifest.file_size_mismatches().is_empty());
+
+        // As a manifest edited through JSON could claim
+        manifest.file_list.entries[1].file_size = 100;
+
+        let mismatches = manifest.file_size_mismatches();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].filename(), "b.bin");
+        assert_eq!(mismatches[0].recompute_file_size(), 2);
+    }
+
+    #[test]
+    fn serialize_with_hash_writes_the_given_hash() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        let hash = shared::FSHAHash::new_from_hashable(b"not the body");
+
+        let bytes = manifest.serialize_with_hash(hash.clone()).unwrap();
+
+        // The hash follows the magic, header size and both data sizes
+        assert_eq!(bytes[16..36], hash.data);
+    }
+
+    #[test]
+    fn serialize_body_matches_the_hashed_body() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        manifest.unknown_trailing = vec![1, 2, 3];
+
+        let (hashed_body, hash) = manifest.serialize_body_with_hash();
+
+        assert_eq!(manifest.serialize_body(), hashed_body);
+        assert_eq!(
+            manifest.serialize_with_hash(hash).unwrap(),
+            manifest.serialize().unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_trailing_sections_roundtrip_when_kept() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+        // A section from a newer format: its size, version and some data
+        manifest.unknown_trailing = vec![9, 0, 0, 0, 0, 0xAB, 0xCD, 0xEF, 0x01];
+        let bytes = manifest.serialize().unwrap();
+
+        let keep = ParseOptions {
+            keep_unknown_trailing: true,
+            ..Default::default()
+        };
+        let (kept, warnings) = FManifestParser::with_options(&bytes, keep)
+            .parse_with_warnings()
+            .unwrap();
+        assert!(warnings.contains(&ParseWarning::UnknownTrailingData(9)));
+        assert_eq!(kept.unknown_trailing, manifest.unknown_trailing);
+        assert_eq!(kept.serialize().unwrap(), bytes);
+
+        let dropped = FManifestParser::new(&bytes).parse().unwrap();
+        assert!(dropped.unknown_trailing.is_empty());
+    }
+
+    #[test]
+    fn symlinks_lists_only_the_linked_files() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcd"), ("link.bin", b"")]);
+        manifest.file_list.entries[1].syslink_target = "a.bin".to_owned();
+        let manifest = fixtures::reparsed(&manifest);
+
+        let symlinks = manifest.symlinks();
+
+        assert_eq!(symlinks.len(), 1);
+        assert_eq!(symlinks[0].filename(), "link.bin");
+        assert_eq!(symlinks[0].syslink_target(), "a.bin");
+        assert!(!manifest.find_file("a.bin").unwrap().is_symlink());
+    }
+
+    #[test]
+    fn strip_extra_hashes_shrinks_the_manifest() {
+        let mut manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")]);
+        fixtures::add_extra_hashes(&mut manifest);
+        let original_size = manifest.serialize().unwrap().len();
+
+        manifest.strip_extra_hashes();
+        let stripped = manifest.serialize().unwrap();
+        assert!(stripped.len() < original_size);
+
+        let reparsed = parse(&stripped).unwrap();
+        assert_eq!(reparsed.file_list.version(), 0);
+        for file in reparsed.file_list.entries() {
+            assert!(file.md5_hash().is_none());
+            assert!(file.sha256_hash().is_none());
+            assert!(file.mime_type().is_none());
+            assert_ne!(*file.hash(), shared::FSHAHash::default());
+        }
+    }
 }
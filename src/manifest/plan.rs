@@ -0,0 +1,289 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::{file_manifest::FFileManifest, shared::FGuid, FManifest};
+
+/// Everything a downloader needs to fetch and assemble a single file
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChunkPlan {
+    pub filename: String,
+    pub file_size: u64,
+    pub parts: Vec<ChunkPartPlan>,
+}
+
+/// A single chunk part of a FileChunkPlan, joined with its chunk info.
+/// `cloud_path` and `compressed_size` are `None` if the chunk is missing from the chunk list.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPartPlan {
+    pub guid: String,
+    pub chunk_offset: u32,
+    pub size: u32,
+    pub file_offset: u64,
+    pub cloud_path: Option<String>,
+    pub compressed_size: Option<i64>,
+}
+
+impl FileChunkPlan {
+    pub fn new(manifest: &FManifest, file: &FFileManifest) -> FileChunkPlan {
+        let feature_level = manifest.meta.feature_level();
+
+        let parts = file
+            .chunk_parts()
+            .iter()
+            .map(|part| {
+                let chunk = manifest.chunk_list.find_by_guid(part.guid());
+
+                ChunkPartPlan {
+                    guid: part.guid().to_hex_lower(),
+                    chunk_offset: part.offset(),
+                    size: part.size(),
+                    file_offset: part.file_offset(),
+                    cloud_path: chunk.map(|chunk| chunk.cloud_path(feature_level)),
+                    compressed_size: chunk.map(|chunk| chunk.compressed_size()),
+                }
+            })
+            .collect();
+
+        FileChunkPlan {
+            filename: file.filename().to_owned(),
+            file_size: file.file_size(),
+            parts,
+        }
+    }
+}
+
+/// The chunks still missing to complete an install, in the order the files need them
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadPlan {
+    pub chunks: Vec<ChunkDownload>,
+    /// Sum of the compressed sizes, which is what gets downloaded
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkDownload {
+    pub guid: String,
+    pub cloud_path: String,
+    pub compressed_size: i64,
+}
+
+impl DownloadPlan {
+    /// Collects the chunks of the files selected by `tags` which `have` doesn't report as present.
+    ///
+    /// Untagged files are always selected, and an empty `tags` selects every file.
+    /// Chunks missing from the chunk list can't be downloaded and are left out.
+    pub fn new(
+        manifest: &FManifest,
+        have: impl Fn(&FGuid) -> bool,
+        tags: &[String],
+    ) -> DownloadPlan {
+        let feature_level = manifest.meta.feature_level();
+
+        let is_selected = |file: &FFileManifest| {
+            tags.is_empty()
+                || file.install_tags().is_empty()
+                || file.install_tags().iter().any(|tag| tags.contains(tag))
+        };
+
+        let mut seen = HashSet::new();
+        let mut chunks = Vec::new();
+        let mut total_bytes = 0u64;
+
+        for file in manifest
+            .file_list
+            .entries()
+            .iter()
+            .filter(|file| is_selected(file))
+        {
+            for part in file.chunk_parts() {
+                if !seen.insert(part.guid()) || have(part.guid()) {
+                    continue;
+                }
+
+                if let Some(chunk) = manifest.chunk_list.find_by_guid(part.guid()) {
+                    total_bytes += chunk.compressed_size().max(0) as u64;
+                    chunks.push(ChunkDownload {
+                        guid: chunk.guid().to_hex_lower(),
+                        cloud_path: chunk.cloud_path(feature_level),
+                        compressed_size: chunk.compressed_size(),
+                    });
+                }
+            }
+        }
+
+        DownloadPlan {
+            chunks,
+            total_bytes,
+        }
+    }
+}
+
+/// Orders the chunks of every file so that files complete as early as possible.
+///
+/// The file missing the fewest chunks is picked next and its missing chunks are queued,
+/// which may complete other files sharing them. Each chunk appears once.
+pub fn chunk_order(manifest: &FManifest) -> Vec<FGuid> {
+    let files = manifest.file_list.entries();
+
+    let file_chunks: Vec<HashSet<FGuid>> = files
+        .iter()
+        .map(|file| file.chunk_parts().iter().map(|part| *part.guid()).collect())
+        .collect();
+
+    let mut files_by_chunk: HashMap<FGuid, Vec<usize>> = HashMap::new();
+    for (index, chunks) in file_chunks.iter().enumerate() {
+        for guid in chunks {
+            files_by_chunk.entry(*guid).or_default().push(index);
+        }
+    }
+
+    let mut missing: Vec<usize> = file_chunks.iter().map(HashSet::len).collect();
+    // Entries go stale when a file's missing count drops, those are skipped when popped
+    let mut queue: BinaryHeap<Reverse<(usize, usize)>> = missing
+        .iter()
+        .enumerate()
+        .map(|(index, count)| Reverse((*count, index)))
+        .collect();
+
+    let mut queued = HashSet::new();
+    let mut order = Vec::new();
+
+    while let Some(Reverse((count, index))) = queue.pop() {
+        if count != missing[index] || count == 0 {
+            continue;
+        }
+
+        // Part order, so the file can be written sequentially as its chunks arrive
+        for part in files[index].chunk_parts() {
+            let guid = *part.guid();
+            if !queued.insert(guid) {
+                continue;
+            }
+            order.push(guid);
+
+            for &other in &files_by_chunk[&guid] {
+                missing[other] -= 1;
+                if other != index {
+                    queue.push(Reverse((missing[other], other)));
+                }
+            }
+        }
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{fixtures, shared::FSHAHash};
+
+    #[test]
+    fn file_chunk_plan_joins_parts_with_their_chunks() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh"), ("b.bin", b"ijkl")]);
+
+        let plan = manifest.file_chunk_plan("a.bin").unwrap();
+
+        assert_eq!(plan.filename, "a.bin");
+        assert_eq!(plan.file_size, 8);
+        assert_eq!(plan.parts.len(), 2);
+        for (part, chunk_part) in plan
+            .parts
+            .iter()
+            .zip(manifest.file_list.entries()[0].chunk_parts())
+        {
+            let chunk = manifest.chunk_list.find_by_guid(chunk_part.guid()).unwrap();
+
+            assert_eq!(part.guid, chunk_part.guid().to_hex_lower());
+            assert_eq!(part.chunk_offset, 0);
+            assert_eq!(part.size, 4);
+            assert_eq!(part.file_offset, chunk_part.file_offset());
+            assert_eq!(
+                part.cloud_path.as_deref(),
+                Some(chunk.cloud_path(manifest.meta.feature_level()).as_str())
+            );
+            assert_eq!(part.compressed_size, Some(chunk.compressed_size()));
+        }
+        assert_eq!(plan.parts[1].file_offset, 4);
+
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["fileSize"], 8);
+        assert!(json["parts"][0]["cloudPath"]
+            .as_str()
+            .unwrap()
+            .starts_with("ChunksV4/"));
+    }
+
+    #[test]
+    fn file_chunk_plan_of_a_missing_file() {
+        let manifest = fixtures::manifest(&[("a.bin", b"abcdefgh")]);
+
+        assert!(manifest.file_chunk_plan("b.bin").is_none());
+    }
+
+    #[test]
+    fn download_plan_skips_unselected_files_and_present_chunks() {
+        let mut manifest = fixtures::manifest(&[
+            ("core.bin", b"abcd"),
+            ("hd.bin", b"efghijkl"),
+            ("fr.bin", b"mnop"),
+        ]);
+        manifest.file_list.entries[1].install_tags = vec!["hd".to_string()];
+        manifest.file_list.entries[2].install_tags = vec!["fr".to_string()];
+
+        let guid_of = |index: usize| *manifest.chunk_list.chunks()[index].guid();
+        let (core, have, hd) = (guid_of(0), guid_of(1), guid_of(2));
+
+        let plan = manifest.download_plan(|guid| *guid == have, &["hd".to_string()]);
+
+        let guids = plan
+            .chunks
+            .iter()
+            .map(|chunk| chunk.guid.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(guids, [core.to_hex_lower(), hd.to_hex_lower()]);
+        assert_eq!(
+            plan.total_bytes,
+            plan.chunks
+                .iter()
+                .map(|chunk| chunk.compressed_size as u64)
+                .sum::<u64>()
+        );
+        assert!(plan.chunks[0].cloud_path.starts_with("ChunksV4/"));
+    }
+
+    #[test]
+    fn chunk_order_completes_files_missing_the_fewest_chunks_first() {
+        let manifest = fixtures::manifest(&[
+            ("big.bin", b"abcdefghijkl"),
+            ("small.bin", b"mnop"),
+            ("shared.bin", b"efghabcd"),
+        ]);
+        let guid_of = |data: &[u8]| {
+            *manifest
+                .chunk_list
+                .chunks()
+                .iter()
+                .find(|chunk| *chunk.sha_hash() == FSHAHash::new_from_hashable(data))
+                .unwrap()
+                .guid()
+        };
+
+        // Once shared.bin is complete, big.bin only misses "ijkl"
+        assert_eq!(
+            manifest.suggest_chunk_order(),
+            [
+                guid_of(b"mnop"),
+                guid_of(b"efgh"),
+                guid_of(b"abcd"),
+                guid_of(b"ijkl")
+            ]
+        );
+    }
+}
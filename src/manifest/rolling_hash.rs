@@ -0,0 +1,84 @@
+//! Port of Unreal's FRollingHash, the 64 bit polynomial hash stored in FChunkInfo::hash
+//! and used to find chunk boundaries.
+
+use std::collections::VecDeque;
+
+const HASH_POLY_64: u64 = 0xC96C5795D7870F42;
+
+const fn build_hash_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut value = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            if value & 1 == 1 {
+                value >>= 1;
+                value ^= HASH_POLY_64;
+            } else {
+                value >>= 1;
+            }
+            j += 1;
+        }
+        table[i] = value;
+        i += 1;
+    }
+    table
+}
+
+pub const HASH_TABLE: [u64; 256] = build_hash_table();
+
+/// Returns the rolling hash of a whole data set, as done by Unreal's FRollingHash::GetHashForDataSet
+pub fn get_hash_for_data_set(data: &[u8]) -> u64 {
+    data.iter().fold(0u64, |hash, byte| {
+        hash.rotate_left(1) ^ HASH_TABLE[*byte as usize]
+    })
+}
+
+/// A hash over the last `window_size` consumed bytes, updated in constant time per byte
+#[derive(Debug, Clone)]
+pub struct FRollingHash {
+    window_size: u32,
+    hash: u64,
+    window: VecDeque<u8>,
+}
+
+impl FRollingHash {
+    pub fn new(window_size: u32) -> FRollingHash {
+        FRollingHash {
+            window_size,
+            hash: 0,
+            window: VecDeque::with_capacity(window_size as usize),
+        }
+    }
+
+    /// Adds a byte to the window, rolling out the oldest one once the window is full
+    pub fn consume_byte(&mut self, byte: u8) {
+        self.hash = self.hash.rotate_left(1) ^ HASH_TABLE[byte as usize];
+        self.window.push_back(byte);
+
+        if self.window.len() > self.window_size as usize {
+            if let Some(outgoing) = self.window.pop_front() {
+                self.hash ^= HASH_TABLE[outgoing as usize].rotate_left(self.window_size);
+            }
+        }
+    }
+
+    /// Returns whether `window_size` bytes have been consumed
+    pub fn is_full(&self) -> bool {
+        self.window.len() == self.window_size as usize
+    }
+
+    pub fn window_size(&self) -> u32 {
+        self.window_size
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn clear(&mut self) {
+        self.hash = 0;
+        self.window.clear();
+    }
+}
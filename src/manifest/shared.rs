@@ -12,10 +12,12 @@ pub const SHA1_DIGEST_SIZE: usize = 20;
 pub const MD5_DIGEST_SIZE: usize = 16;
 pub const SHA256_DIGEST_SIZE: usize = 32;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 
 /// This type is the same type used in the Unreal Engine 4 source code to represent a GUID.
 /// Learn more here https://docs.unrealengine.com/4.27/en-US/API/Runtime/Core/Misc/FGuid
+///
+/// Serialized to JSON as 32 lowercase hex digits, like the hashes.
 
 pub struct FGuid {
     ///Private:
@@ -34,6 +36,76 @@ impl std::fmt::Debug for FGuid {
     }
 }
 
+impl FGuid {
+    /// Returns the 32 hex digits of the GUID in uppercase, as Unreal formats it and CDN chunk paths expect
+    pub fn to_hex_upper(&self) -> String {
+        self.to_string()
+    }
+
+    /// Returns the 32 hex digits of the GUID in lowercase, the casing used for hashes in the JSON output
+    pub fn to_hex_lower(&self) -> String {
+        self.to_string().to_lowercase()
+    }
+
+    /// Parses the 32 hex digits of a GUID, in either case
+    pub fn from_hex(hex: &str) -> Option<FGuid> {
+        if hex.len() != 32 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let component = |index: usize| u32::from_str_radix(&hex[index * 8..index * 8 + 8], 16).ok();
+
+        Some(FGuid {
+            a: component(0)?,
+            b: component(1)?,
+            c: component(2)?,
+            d: component(3)?,
+        })
+    }
+}
+
+impl serde::Serialize for FGuid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_hex_lower())
+    }
+}
+
+/// The JSON forms of a GUID: hex digits, or the components object older versions of the crate wrote
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum FGuidRepr {
+    Hex(String),
+    Components { a: u32, b: u32, c: u32, d: u32 },
+}
+
+impl<'de> serde::Deserialize<'de> for FGuid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match FGuidRepr::deserialize(deserializer)? {
+            FGuidRepr::Hex(hex) => FGuid::from_hex(&hex).ok_or_else(|| {
+                serde::de::Error::custom(format!("Expected 32 hex digits, got {}", hex))
+            }),
+            FGuidRepr::Components { a, b, c, d } => Ok(FGuid { a, b, c, d }),
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for FGuid {
+    fn schema_name() -> String {
+        "FGuid".to_owned()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        hex_string_schema(16)
+    }
+}
+
 impl ToString for FGuid {
     fn to_string(&self) -> String {
         let mut normalize = |str: String| -> String {
@@ -53,6 +125,7 @@ impl ToString for FGuid {
 }
 
 #[derive(Debug, PartialEq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum EManifestStorageFlags {
     // Stored as raw data.
     None = 0,
@@ -62,6 +135,18 @@ pub enum EManifestStorageFlags {
     Encrypted = 1 << 1,
 }
 
+impl EManifestStorageFlags {
+    /// Returns `None` for values that aren't a known storage flag instead of panicking
+    pub fn from_u8(value: u8) -> Option<EManifestStorageFlags> {
+        match value {
+            0 => Some(EManifestStorageFlags::None),
+            1 => Some(EManifestStorageFlags::Compressed),
+            2 => Some(EManifestStorageFlags::Encrypted),
+            _ => None,
+        }
+    }
+}
+
 impl From<u8> for EManifestStorageFlags {
     fn from(value: u8) -> Self {
         match value {
@@ -88,6 +173,18 @@ impl Default for EChunkStorageFlags {
     }
 }
 
+impl EChunkStorageFlags {
+    /// Returns `None` for values that aren't a known storage flag instead of panicking
+    pub fn from_u8(value: u8) -> Option<EChunkStorageFlags> {
+        match value {
+            0 => Some(EChunkStorageFlags::None),
+            1 => Some(EChunkStorageFlags::Compressed),
+            2 => Some(EChunkStorageFlags::Encrypted),
+            _ => None,
+        }
+    }
+}
+
 impl From<u8> for EChunkStorageFlags {
     fn from(value: u8) -> Self {
         match value {
@@ -112,6 +209,19 @@ pub enum EChunkHashFlags {
     Both,
 }
 
+impl EChunkHashFlags {
+    /// Returns `None` for values that aren't a known hash type instead of panicking
+    pub fn from_u8(value: u8) -> Option<EChunkHashFlags> {
+        match value {
+            0 => Some(EChunkHashFlags::None),
+            1 => Some(EChunkHashFlags::RollingPoly64),
+            2 => Some(EChunkHashFlags::Sha1),
+            3 => Some(EChunkHashFlags::Both),
+            _ => None,
+        }
+    }
+}
+
 impl From<u8> for EChunkHashFlags {
     fn from(value: u8) -> Self {
         match value {
@@ -183,6 +293,7 @@ impl PartialEq for EChunkVersion {
  * An enum type to describe supported features of a certain manifest.
  */
 #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum EFeatureLevel {
     // The original version.
     Original,
@@ -287,6 +398,29 @@ impl EFeatureLevel {
         }
     }
 
+    /// Returns the CDN directory chunks of a manifest at this feature level are stored in
+    pub fn chunk_sub_dir(&self) -> &'static str {
+        let level = self.effective().to_i32();
+        if level < EFeatureLevel::DataFileRenames.to_i32() {
+            "Chunks"
+        } else if level < EFeatureLevel::ChunkCompressionSupport.to_i32() {
+            "ChunksV2"
+        } else if level < EFeatureLevel::VariableSizeChunksWithoutWindowSizeChunkInfo.to_i32() {
+            "ChunksV3"
+        } else {
+            "ChunksV4"
+        }
+    }
+
+    /// Returns the feature level that should be used for version gating.
+    /// `BrokenJsonVersion` (255) is treated as `StoresChunkFileSizes`, like Unreal does.
+    pub fn effective(&self) -> EFeatureLevel {
+        match self {
+            EFeatureLevel::BrokenJsonVersion => EFeatureLevel::StoresChunkFileSizes,
+            _ => *self,
+        }
+    }
+
     pub fn from_i32(value: i32) -> Option<EFeatureLevel> {
         match value {
             0 => Some(EFeatureLevel::Original),
@@ -362,6 +496,17 @@ impl<'de, const DIGEST_LENGTH: usize> serde::Deserialize<'de> for UnknownHash<DI
     }
 }
 
+#[cfg(feature = "schemars")]
+impl<const DIGEST_LENGTH: usize> schemars::JsonSchema for UnknownHash<DIGEST_LENGTH> {
+    fn schema_name() -> String {
+        format!("UnknownHash{}", DIGEST_LENGTH)
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        hex_string_schema(DIGEST_LENGTH)
+    }
+}
+
 impl<const DIGEST_LENGTH: usize> UnknownHash<DIGEST_LENGTH> {
     pub fn new(data: [u8; DIGEST_LENGTH]) -> UnknownHash<DIGEST_LENGTH> {
         UnknownHash { data }
@@ -414,6 +559,34 @@ impl serde::Serialize for FSHAHash {
     }
 }
 
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for FSHAHash {
+    fn schema_name() -> String {
+        "FSHAHash".to_owned()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        hex_string_schema(SHA1_DIGEST_SIZE)
+    }
+}
+
+/// Hashes are serialized as hex strings, two characters per byte
+#[cfg(feature = "schemars")]
+fn hex_string_schema(digest_size: usize) -> schemars::schema::Schema {
+    use schemars::schema::{InstanceType, SchemaObject, StringValidation};
+
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            min_length: Some(digest_size as u32 * 2),
+            max_length: Some(digest_size as u32 * 2),
+            pattern: Some("^[0-9a-fA-F]*$".to_owned()),
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
 impl<'de> serde::Deserialize<'de> for FSHAHash {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -479,6 +652,10 @@ impl FSHAHash {
     }
 
     pub fn to_hex_string(&self) -> String {
+        self.to_hex_lower()
+    }
+
+    pub fn to_hex_lower(&self) -> String {
         let mut result = String::with_capacity(SHA1_DIGEST_SIZE * 2);
         for byte in self.data.iter() {
             result.push_str(&format!("{:02x}", byte));
@@ -486,6 +663,10 @@ impl FSHAHash {
         result
     }
 
+    pub fn to_hex_upper(&self) -> String {
+        self.to_hex_lower().to_uppercase()
+    }
+
     pub fn to_hash(&self) -> u64 {
         let mut hasher = DefaultHasher::new();
 
@@ -544,3 +725,72 @@ impl ByteWritable for EFeatureLevel {
         writer.write(&self.to_i32());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: FGuid = FGuid {
+        a: 0x0123_ABCD,
+        b: 0xF,
+        c: 0xDEAD_BEEF,
+        d: 0,
+    };
+
+    #[test]
+    fn guids_and_hashes_agree_on_casing() {
+        let hash = FSHAHash::new([0xAB; SHA1_DIGEST_SIZE]);
+
+        assert_eq!(GUID.to_hex_upper(), "0123ABCD0000000FDEADBEEF00000000");
+        assert_eq!(GUID.to_hex_lower(), "0123abcd0000000fdeadbeef00000000");
+        assert_eq!(hash.to_hex_lower(), "ab".repeat(SHA1_DIGEST_SIZE));
+        assert_eq!(hash.to_hex_upper(), "AB".repeat(SHA1_DIGEST_SIZE));
+
+        let json = serde_json::to_string(&(GUID, hash.clone())).unwrap();
+        assert_eq!(
+            json,
+            format!("[\"{}\",\"{}\"]", GUID.to_hex_lower(), hash.to_hex_lower())
+        );
+    }
+
+    #[test]
+    fn guids_round_trip_through_json() {
+        let json = serde_json::to_string(&GUID).unwrap();
+        assert_eq!(serde_json::from_str::<FGuid>(&json).unwrap(), GUID);
+
+        let upper = format!("\"{}\"", GUID.to_hex_upper());
+        assert_eq!(serde_json::from_str::<FGuid>(&upper).unwrap(), GUID);
+
+        let components = r#"{"a":19114957,"b":15,"c":3735928559,"d":0}"#;
+        assert_eq!(serde_json::from_str::<FGuid>(components).unwrap(), GUID);
+
+        assert!(serde_json::from_str::<FGuid>("\"0123abcd\"").is_err());
+        let not_hex = format!("\"+{}\"", "0".repeat(31));
+        assert!(serde_json::from_str::<FGuid>(&not_hex).is_err());
+    }
+
+    #[test]
+    fn guids_are_ordered_by_their_components() {
+        let guid = |a, b, c, d| FGuid { a, b, c, d };
+        let mut guids = vec![
+            guid(2, 0, 0, 0),
+            guid(1, 5, 0, 0),
+            guid(1, 0, 0, 9),
+            guid(1, 0, 3, 0),
+            guid(1, 0, 0, 1),
+        ];
+
+        guids.sort();
+
+        assert_eq!(
+            guids,
+            [
+                guid(1, 0, 0, 1),
+                guid(1, 0, 0, 9),
+                guid(1, 0, 3, 0),
+                guid(1, 5, 0, 0),
+                guid(2, 0, 0, 0),
+            ]
+        );
+    }
+}
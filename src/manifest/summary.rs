@@ -0,0 +1,310 @@
+use crate::helper;
+
+use super::{header::FManifestHeader, meta::FManifestMeta, shared::EFeatureLevel, FManifest};
+
+/// A lightweight overview of a parsed manifest, meant to be shown to users without
+/// shipping the whole file and chunk lists over to JS.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestSummary {
+    pub app_name: String,
+    pub build_version: String,
+    pub file_count: usize,
+    pub chunk_count: usize,
+    pub total_file_size: u64,
+    pub total_chunk_parts: usize,
+    pub avg_parts_per_file: f64,
+    pub max_parts_in_single_file: usize,
+    pub most_fragmented_file: Option<String>,
+}
+
+impl ManifestSummary {
+    /// Builds the summary in a single pass over the file list
+    pub fn new(manifest: &FManifest) -> ManifestSummary {
+        let entries = manifest.file_list.entries();
+
+        let mut total_file_size = 0u64;
+        let mut total_chunk_parts = 0usize;
+        let mut max_parts_in_single_file = 0usize;
+        let mut most_fragmented_file = None;
+
+        for entry in entries {
+            let part_count = entry.chunk_parts().len();
+
+            total_file_size += entry.file_size();
+            total_chunk_parts += part_count;
+
+            if part_count > max_parts_in_single_file {
+                max_parts_in_single_file = part_count;
+                most_fragmented_file = Some(entry.filename());
+            }
+        }
+
+        let avg_parts_per_file = if entries.is_empty() {
+            0.0
+        } else {
+            total_chunk_parts as f64 / entries.len() as f64
+        };
+
+        ManifestSummary {
+            app_name: manifest.meta.app_name().to_owned(),
+            build_version: manifest.meta.build_version().to_owned(),
+            file_count: entries.len(),
+            chunk_count: manifest.chunk_list.chunks().len(),
+            total_file_size,
+            total_chunk_parts,
+            avg_parts_per_file,
+            max_parts_in_single_file,
+            most_fragmented_file: most_fragmented_file.map(str::to_owned),
+        }
+    }
+}
+
+/// The declared size, version and entry count of a single manifest section
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionInfo {
+    pub size: u32,
+    pub version: u8,
+    /// `None` for sections that don't hold a list of entries
+    pub entry_count: Option<usize>,
+}
+
+/// The structure of every section of a manifest, to compare manifests at a glance
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionReport {
+    pub meta: SectionInfo,
+    pub chunk_list: SectionInfo,
+    pub file_list: SectionInfo,
+    pub custom_fields: SectionInfo,
+}
+
+impl SectionReport {
+    pub fn new(manifest: &FManifest) -> SectionReport {
+        SectionReport {
+            meta: SectionInfo {
+                size: manifest.meta._size,
+                version: manifest.meta._data_version,
+                entry_count: None,
+            },
+            chunk_list: SectionInfo {
+                size: manifest.chunk_list._size,
+                version: manifest.chunk_list._version,
+                entry_count: Some(manifest.chunk_list.chunks().len()),
+            },
+            file_list: SectionInfo {
+                size: manifest.file_list._size,
+                version: manifest.file_list._version,
+                entry_count: Some(manifest.file_list.entries().len()),
+            },
+            custom_fields: SectionInfo {
+                size: manifest.custom_fields._size,
+                version: manifest.custom_fields._version,
+                entry_count: Some(manifest.custom_fields.fields.len()),
+            },
+        }
+    }
+}
+
+/// What a client needs to know to decide whether it can handle a manifest,
+/// built from the header and meta only.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub feature_level: i32,
+    pub feature_level_name: String,
+    pub is_file_data: bool,
+    pub is_chunked: bool,
+    pub supports_variable_chunks: bool,
+}
+
+impl VersionInfo {
+    pub fn new(header: &FManifestHeader, meta: &FManifestMeta) -> VersionInfo {
+        let version = header.version();
+
+        VersionInfo {
+            feature_level: version.to_i32(),
+            feature_level_name: format!("{:?}", version),
+            is_file_data: meta.is_file_data(),
+            is_chunked: !meta.is_file_data(),
+            supports_variable_chunks: version.effective().to_i32()
+                >= EFeatureLevel::VariableSizeChunksWithoutWindowSizeChunkInfo.to_i32(),
+        }
+    }
+}
+
+/// Every version number of a manifest. The feature level describes what the app supports,
+/// while each section has its own data version deciding how it is laid out.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionVersions {
+    pub feature_level: i32,
+    pub meta: u8,
+    pub chunk_list: u8,
+    pub file_list: u8,
+    pub custom_fields: u8,
+}
+
+impl SectionVersions {
+    pub fn new(manifest: &FManifest) -> SectionVersions {
+        SectionVersions {
+            feature_level: manifest.header.version().to_i32(),
+            meta: manifest.meta.data_version(),
+            chunk_list: manifest.chunk_list.version(),
+            file_list: manifest.file_list.version(),
+            custom_fields: manifest.custom_fields.version(),
+        }
+    }
+}
+
+/// How to start the app and its prerequisites installer, as stored in the meta section
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchInfo {
+    pub launch_exe: String,
+    pub launch_command: String,
+    pub prereq_path: String,
+    pub prereq_args: String,
+}
+
+impl LaunchInfo {
+    pub fn new(meta: &FManifestMeta) -> LaunchInfo {
+        LaunchInfo {
+            launch_exe: meta.launch_exe().to_owned(),
+            launch_command: meta.launch_command().to_owned(),
+            prereq_path: meta.prereq_path().to_owned(),
+            prereq_args: meta.prereq_args().to_owned(),
+        }
+    }
+
+    /// Returns the executable followed by the launch command split into arguments,
+    /// with Windows quoting rules. See `helper::split_command_line`
+    pub fn launch_argv(&self) -> Vec<String> {
+        argv(&self.launch_exe, &self.launch_command)
+    }
+
+    /// Same as `launch_argv`, for the prerequisites installer
+    pub fn prereq_argv(&self) -> Vec<String> {
+        argv(&self.prereq_path, &self.prereq_args)
+    }
+}
+
+/// Returns `program` followed by the arguments of `command_line`, leaving out an empty `program`
+fn argv(program: &str, command_line: &str) -> Vec<String> {
+    let mut argv = Vec::new();
+    if !program.is_empty() {
+        argv.push(program.to_owned());
+    }
+    argv.extend(helper::split_command_line(command_line));
+    argv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::fixtures;
+
+    #[test]
+    fn summary_reports_the_most_fragmented_file() {
+        let manifest = fixtures::manifest(&[
+            ("small.bin", b"abcdefgh"),
+            ("large.bin", b"ijklmnopqrst"),
+            ("tiny.bin", b"uv"),
+        ]);
+
+        let summary = ManifestSummary::new(&manifest);
+
+        assert_eq!(summary.file_count, 3);
+        assert_eq!(summary.total_file_size, 22);
+        assert_eq!(summary.total_chunk_parts, 6);
+        assert_eq!(summary.avg_parts_per_file, 2.0);
+        assert_eq!(summary.max_parts_in_single_file, 3);
+        assert_eq!(summary.most_fragmented_file.as_deref(), Some("large.bin"));
+    }
+
+    #[test]
+    fn summary_of_an_empty_manifest() {
+        let summary = ManifestSummary::new(&fixtures::manifest(&[]));
+
+        assert_eq!(summary.total_chunk_parts, 0);
+        assert_eq!(summary.avg_parts_per_file, 0.0);
+        assert_eq!(summary.most_fragmented_file, None);
+    }
+
+    #[test]
+    fn section_report_of_a_known_manifest() {
+        let manifest = fixtures::reparsed(&fixtures::manifest(&[("a.bin", b"abcdefgh")]));
+
+        let section = |size, version, entry_count| SectionInfo {
+            size,
+            version,
+            entry_count,
+        };
+        assert_eq!(
+            manifest.section_report(),
+            SectionReport {
+                meta: section(113, 2, None),
+                chunk_list: section(123, 0, Some(2)),
+                file_list: section(108, 0, Some(1)),
+                custom_fields: section(9, 0, Some(0)),
+            }
+        );
+    }
+
+    #[test]
+    fn version_info_of_a_known_manifest() {
+        let bytes = fixtures::manifest(&[("a.bin", b"abcdefgh")])
+            .serialize()
+            .unwrap();
+        let (header, meta) = crate::manifest::parse_meta_only(&bytes).unwrap();
+
+        let info = VersionInfo::new(&header, &meta);
+
+        assert_eq!(
+            info.feature_level,
+            EFeatureLevel::UsesBuildTimeGeneratedBuildId.to_i32()
+        );
+        assert_eq!(info.feature_level_name, "UsesBuildTimeGeneratedBuildId");
+        assert!(!info.is_file_data);
+        assert!(info.is_chunked);
+        assert!(info.supports_variable_chunks);
+    }
+
+    #[test]
+    fn section_versions_of_a_known_manifest() {
+        let manifest = fixtures::reparsed(&fixtures::manifest(&[("a.bin", b"abcdefgh")]));
+
+        assert_eq!(
+            manifest.section_versions(),
+            SectionVersions {
+                feature_level: EFeatureLevel::UsesBuildTimeGeneratedBuildId.to_i32(),
+                meta: 2,
+                chunk_list: 0,
+                file_list: 0,
+                custom_fields: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn launch_argv_splits_a_quoted_command_line() {
+        let info = LaunchInfo {
+            launch_exe: "Game/Binaries/Game.exe".to_owned(),
+            launch_command: r#"-epicapp=Game -map "Main Menu""#.to_owned(),
+            prereq_path: String::new(),
+            prereq_args: "/quiet".to_owned(),
+        };
+
+        assert_eq!(
+            info.launch_argv(),
+            vec![
+                "Game/Binaries/Game.exe",
+                "-epicapp=Game",
+                "-map",
+                "Main Menu"
+            ]
+        );
+        assert_eq!(info.prereq_argv(), vec!["/quiet"]);
+    }
+}
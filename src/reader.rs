@@ -15,6 +15,8 @@ use crate::{
 pub struct ByteReader {
     data: Vec<u8>,
     position: usize,
+    /// Fall back to a lossy conversion for strings that aren't properly nul-terminated
+    lenient_strings: bool,
 }
 
 impl ByteReader {
@@ -25,7 +27,16 @@ impl ByteReader {
     /// * `data` - A Vec<u8> containing the binary data
     ///
     pub fn new(data: Vec<u8>) -> ByteReader {
-        ByteReader { data, position: 0 }
+        ByteReader {
+            data,
+            position: 0,
+            lenient_strings: false,
+        }
+    }
+
+    /// Accept strings missing their trailing nul or containing interior nuls, see `ParseOptions::lenient_strings`
+    pub fn set_lenient_strings(&mut self, lenient: bool) {
+        self.lenient_strings = lenient;
     }
 
     /// This function is used to read a certain amount of bytes from the binary data and return it as a Vec<u8>
@@ -62,6 +73,21 @@ impl ByteReader {
         self.data.len()
     }
 
+    /// This function is used to get the amount of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.position)
+    }
+
+    /// This function is used to check that `count` items of at least `min_item_size` bytes each
+    /// can fit in the remaining data, before reserving memory or looping over a count read from the file
+    pub fn check_count(&self, count: usize, min_item_size: usize) -> ParseResult<()> {
+        if count.saturating_mul(min_item_size) > self.remaining() {
+            return Err(ParseError::Overflow);
+        }
+
+        Ok(())
+    }
+
     pub fn seek(&mut self, position: usize) {
         self.position = position;
     }
@@ -69,6 +95,7 @@ impl ByteReader {
     /// This function is used to read an array. It takes a closure that will be used to read each item of the array
     /// # Exemples (from src/manifest/meta.rs)
     /// ```
+    /// # use epic_manifest_wasm::{reader::ByteReader, ParseResult};
     /// fn get_prereq_ids(reader: &mut ByteReader) -> ParseResult<Vec<String>> {
     ///     reader.read_array(|reader| reader.read())
     /// }
@@ -76,19 +103,35 @@ impl ByteReader {
 
     pub fn read_array<T>(
         &mut self,
-        mut read_item: impl FnMut(&mut Self) -> ParseResult<T>,
+        read_item: impl FnMut(&mut Self) -> ParseResult<T>,
     ) -> ParseResult<Vec<T>> {
         let count = self.read::<u32>()? as usize;
+        self.read_array_with_count(count, read_item)
+    }
 
-        if count == 0 {
-            return Ok(vec![]);
-        } else {
-            let mut result = Vec::with_capacity(count);
-            for _ in 0..count {
-                result.push(read_item(self)?);
-            }
-            Ok(result)
+    /// Same as `read_array`, for arrays whose count isn't stored right before them,
+    /// like the parallel columns sharing the count of their section.
+    /// Every item must take at least one byte: counts larger than the remaining data fail before allocating.
+    pub fn read_array_with_count<T>(
+        &mut self,
+        count: usize,
+        mut read_item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        self.check_count(count, 1)?;
+
+        let mut result = Vec::with_capacity(count);
+        for _ in 0..count {
+            result.push(read_item(self)?);
         }
+        Ok(result)
+    }
+
+    /// This function is used to read a length-prefixed blob: a u32 length followed by that many bytes.
+    /// The length is checked against the remaining data before anything is allocated.
+    pub fn read_blob(&mut self) -> ParseResult<Vec<u8>> {
+        let length = self.read::<u32>()? as usize;
+        self.check_count(length, 1)?;
+        self.read_bytes(length)
     }
 
     pub fn read_remaining(&mut self) -> Vec<u8> {
@@ -209,22 +252,36 @@ impl ByteReadable for String {
         let utf_8 = length > 0;
 
         let string = if utf_8 {
-            let c_string = CString::from_vec_with_nul(reader.read_bytes(length as usize)?)
-                .map_err(|_| ParseError::InvalidData)?;
-
-            c_string
-                .into_string()
-                .map_err(|_| ParseError::InvalidData)?
+            let bytes = reader.read_bytes(length as usize)?;
+
+            match CString::from_vec_with_nul(bytes) {
+                Ok(c_string) => c_string
+                    .into_string()
+                    .map_err(|_| ParseError::InvalidData)?,
+                Err(err) if reader.lenient_strings => {
+                    // Stop at the first nul, like a C reader would, so the string can be written back
+                    let bytes = err.into_bytes();
+                    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                    String::from_utf8_lossy(&bytes[..end]).into_owned()
+                }
+                Err(_) => return Err(ParseError::InvalidData),
+            }
         } else {
             let length = (length * -2) as usize;
             let byte_data = reader.read_bytes(length)?;
 
-            //shouldn't panic
-            unsafe {
-                let u16_string =
-                    U16String::from_ptr(byte_data.as_ptr() as *const u16, length.abs_diff(0));
-                u16_string.to_string_lossy()
+            let mut units = byte_data
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect::<Vec<u16>>();
+
+            // The length covers the nul terminator, which isn't part of the string
+            let end = units.iter().position(|unit| *unit == 0);
+            if !reader.lenient_strings && end != Some(units.len() - 1) {
+                return Err(ParseError::InvalidData);
             }
+            units.truncate(end.unwrap_or(units.len()));
+            U16String::from_vec(units).to_string_lossy()
         };
 
         Ok(string)
@@ -252,3 +309,123 @@ impl ByteReadable for FSHAHash {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf8_string(bytes: &[u8], lenient: bool) -> ParseResult<String> {
+        let mut data = (bytes.len() as i32).to_le_bytes().to_vec();
+        data.extend_from_slice(bytes);
+
+        let mut reader = ByteReader::new(data);
+        reader.set_lenient_strings(lenient);
+        reader.read()
+    }
+
+    #[test]
+    fn strings_without_a_trailing_nul_are_read_when_lenient() {
+        assert!(matches!(
+            utf8_string(b"abc", false),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(utf8_string(b"abc", true).unwrap(), "abc");
+    }
+
+    #[test]
+    fn strings_with_an_interior_nul_are_truncated_when_lenient() {
+        assert!(matches!(
+            utf8_string(b"ab\0cd\0", false),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(utf8_string(b"ab\0cd\0", true).unwrap(), "ab");
+    }
+
+    fn utf16_string(units: &[u16], lenient: bool) -> ParseResult<String> {
+        let mut data = (-(units.len() as i32)).to_le_bytes().to_vec();
+        data.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+
+        let mut reader = ByteReader::new(data);
+        reader.set_lenient_strings(lenient);
+        reader.read()
+    }
+
+    #[test]
+    fn utf16_strings_must_be_properly_nul_terminated_unless_lenient() {
+        let e = 0xe9;
+        assert_eq!(utf16_string(&[e, 0], false).unwrap(), "é");
+
+        assert!(matches!(
+            utf16_string(&[e, 0, e, 0], false),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(utf16_string(&[e, 0, e, 0], true).unwrap(), "é");
+
+        assert!(matches!(
+            utf16_string(&[e, e], false),
+            Err(ParseError::InvalidData)
+        ));
+        assert_eq!(utf16_string(&[e, e], true).unwrap(), "éé");
+    }
+
+    #[test]
+    fn read_array_with_count_matches_a_manual_loop() {
+        let data = [1u32, 2, 3, 4]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let mut manual = Vec::new();
+        let mut reader = ByteReader::new(data.clone());
+        for _ in 0..3 {
+            manual.push(reader.read::<u32>().unwrap());
+        }
+
+        let mut array_reader = ByteReader::new(data);
+        let array = array_reader
+            .read_array_with_count(3, |reader| reader.read::<u32>())
+            .unwrap();
+
+        assert_eq!(array, manual);
+        assert_eq!(array_reader.tell(), reader.tell());
+        assert!(array_reader
+            .read_array_with_count(0, |reader| reader.read::<u32>())
+            .unwrap()
+            .is_empty());
+        assert_eq!(array_reader.tell(), 12);
+    }
+
+    #[test]
+    fn read_blob_reads_a_length_prefixed_blob() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(b"abcd");
+
+        let mut reader = ByteReader::new(data);
+
+        assert_eq!(reader.read_blob().unwrap(), b"abc");
+        assert_eq!(reader.tell(), 7);
+    }
+
+    #[test]
+    fn read_blob_with_a_truncated_length_overflows() {
+        let mut data = u32::MAX.to_le_bytes().to_vec();
+        data.extend_from_slice(b"abcd");
+
+        let mut reader = ByteReader::new(data);
+
+        assert!(matches!(reader.read_blob(), Err(ParseError::Overflow)));
+    }
+
+    #[test]
+    fn read_array_with_an_absurd_count_fails_before_allocating() {
+        let mut data = u32::MAX.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u32.to_le_bytes());
+
+        let mut reader = ByteReader::new(data);
+
+        assert!(matches!(
+            reader.read_array(|reader| reader.read::<u32>()),
+            Err(ParseError::Overflow)
+        ));
+    }
+}
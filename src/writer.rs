@@ -2,7 +2,7 @@ use crate::{
     manifest::shared::{FGuid, FSHAHash},
     ParseResult,
 };
-use std::ffi::CString;
+use sha1::{Digest, Sha1};
 use widestring::U16String;
 
 /// A struct for writing binary data in the same format as the parser expects
@@ -17,6 +17,13 @@ impl ByteWriter {
         ByteWriter { data: Vec::new() }
     }
 
+    /// Creates a new ByteWriter able to hold `capacity` bytes without reallocating
+    pub fn with_capacity(capacity: usize) -> ByteWriter {
+        ByteWriter {
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
     /// Writes bytes to the buffer
     pub fn write_bytes(&mut self, bytes: &[u8]) {
         self.data.extend_from_slice(bytes);
@@ -27,6 +34,11 @@ impl ByteWriter {
         value.write(self);
     }
 
+    /// Overwrites the u32 at `position`, used to back-patch a size field once the data it covers is written
+    pub fn write_u32_at(&mut self, position: usize, value: u32) {
+        self.data[position..position + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
     /// Returns the current position (length of written data)
     pub fn tell(&self) -> usize {
         self.data.len()
@@ -42,6 +54,19 @@ impl ByteWriter {
         &self.data
     }
 
+    /// Writes a string as UTF-16, the way Unreal stores strings that aren't plain ANSI.
+    /// The length prefix is the negated character count including the nul terminator, not a byte count:
+    /// the reader multiplies it by -2 to get the size in bytes.
+    pub fn write_utf16(&mut self, value: &str) {
+        let mut units = U16String::from_str(value).into_vec();
+        units.push(0);
+
+        self.write(&-(units.len() as i32));
+        for unit in &units {
+            self.write(unit);
+        }
+    }
+
     /// Writes an array with count prefix
     pub fn write_array<T: ByteWritable>(&mut self, items: &[T]) {
         self.write(&(items.len() as u32));
@@ -51,6 +76,41 @@ impl ByteWriter {
     }
 }
 
+/// A ByteWriter that computes the SHA1 of its data while it is written.
+///
+/// Sections back-patch their size once written, so bytes are hashed a whole section at a time
+/// rather than as they are written, right after the section is done.
+pub struct HashingWriter {
+    writer: ByteWriter,
+    hasher: Sha1,
+    hashed: usize,
+}
+
+impl HashingWriter {
+    pub fn with_capacity(capacity: usize) -> HashingWriter {
+        HashingWriter {
+            writer: ByteWriter::with_capacity(capacity),
+            hasher: Sha1::new(),
+            hashed: 0,
+        }
+    }
+
+    /// Runs `write_section` on the inner writer, then hashes what it wrote.
+    /// Nothing written before may be modified by `write_section`.
+    pub fn section(&mut self, write_section: impl FnOnce(&mut ByteWriter)) {
+        write_section(&mut self.writer);
+
+        self.hasher.update(&self.writer.as_bytes()[self.hashed..]);
+        self.hashed = self.writer.tell();
+    }
+
+    /// Returns the written data and its SHA1 hash
+    pub fn finish(self) -> (Vec<u8>, FSHAHash) {
+        let hash = FSHAHash::new(self.hasher.finalize().into());
+        (self.writer.into_bytes(), hash)
+    }
+}
+
 /// Trait for types that can be written to a ByteWriter
 pub trait ByteWritable {
     fn write(&self, writer: &mut ByteWriter);
@@ -104,6 +164,18 @@ impl ByteWritable for i8 {
     }
 }
 
+/// Returns how many bytes writing `value` as a String takes: the length prefix, then the nul-terminated
+/// UTF-8 bytes, or the nul-terminated UTF-16 units for strings that aren't plain ASCII
+pub fn string_size(value: &str) -> usize {
+    if value.is_empty() {
+        4
+    } else if value.is_ascii() {
+        4 + value.len() + 1
+    } else {
+        4 + (value.encode_utf16().count() + 1) * 2
+    }
+}
+
 impl ByteWritable for String {
     fn write(&self, writer: &mut ByteWriter) {
         if self.is_empty() {
@@ -111,11 +183,15 @@ impl ByteWritable for String {
             return;
         }
 
-        // Write as UTF-8 (positive length)
-        let c_string = CString::new(self.as_str()).unwrap();
-        let bytes = c_string.into_bytes_with_nul();
-        writer.write(&(bytes.len() as i32));
-        writer.write_bytes(&bytes);
+        if !self.is_ascii() {
+            writer.write_utf16(self);
+            return;
+        }
+
+        // Write as UTF-8 (positive length), the length covers the nul terminator
+        writer.write(&((self.len() + 1) as i32));
+        writer.write_bytes(self.as_bytes());
+        writer.write(&0u8);
     }
 }
 
@@ -133,3 +209,51 @@ impl ByteWritable for FSHAHash {
         writer.write_bytes(&self.data);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ByteReader;
+
+    #[test]
+    fn strings_with_an_interior_nul_are_written_without_panicking() {
+        let mut writer = ByteWriter::new();
+        writer.write(&"ab\0cd".to_string());
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes, [6, 0, 0, 0, b'a', b'b', 0, b'c', b'd', 0]);
+
+        let mut reader = ByteReader::new(bytes);
+        reader.set_lenient_strings(true);
+        assert_eq!(reader.read::<String>().unwrap(), "ab");
+    }
+
+    #[test]
+    fn non_ascii_strings_are_written_as_utf16_with_a_negated_character_count() {
+        let value = "é€".to_string();
+        let mut writer = ByteWriter::new();
+        writer.write(&value);
+        let bytes = writer.into_bytes();
+
+        // Two characters plus the nul terminator
+        assert_eq!(bytes[..4], (-3i32).to_le_bytes());
+        assert_eq!(bytes[4..], [0xe9, 0x00, 0xac, 0x20, 0x00, 0x00]);
+        assert_eq!(bytes.len(), string_size(&value));
+
+        let mut reader = ByteReader::new(bytes);
+        assert_eq!(reader.read::<String>().unwrap(), value);
+    }
+
+    #[test]
+    fn utf16_length_prefix_counts_surrogate_pairs_as_two_units() {
+        let value = "a😀".to_string();
+        let mut writer = ByteWriter::new();
+        writer.write(&value);
+        let bytes = writer.into_bytes();
+
+        assert_eq!(bytes[..4], (-4i32).to_le_bytes());
+        assert_eq!(bytes.len(), string_size(&value));
+
+        let mut reader = ByteReader::new(bytes);
+        assert_eq!(reader.read::<String>().unwrap(), value);
+    }
+}
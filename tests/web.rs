@@ -0,0 +1,269 @@
+//! Tests of the JS facing API, run in a browser or Node with `wasm-pack test --node`
+
+#![cfg(target_arch = "wasm32")]
+
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+use epic_manifest_wasm::{
+    create_manifest,
+    download::{download_file, extract_file},
+    handle::ManifestHandle,
+    manifest::{
+        builder::{ChunkingStrategy, ManifestBuilder},
+        chunks::FChunk,
+        shared::FGuid,
+        FManifest,
+    },
+    manifest_group_histogram, manifest_version_info,
+};
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_test::*;
+
+/// Builds a manifest holding two small files split into 4 byte chunks, along with the chunk files
+/// the CDN would serve, keyed by cloud path. "a.bin" uses its "abcd" chunk twice.
+fn manifest_and_chunk_files() -> (FManifest, HashMap<String, Vec<u8>>) {
+    let mut builder = ManifestBuilder::new("TestApp", "1.0.0");
+    builder
+        .add_file("a.bin", b"abcdabcdefgh", ChunkingStrategy::FixedSize(4))
+        .unwrap();
+    builder
+        .add_file("b.bin", b"ijkl", ChunkingStrategy::FixedSize(4))
+        .unwrap();
+    let (manifest, chunk_data) = builder.build();
+
+    let feature_level = manifest.meta.feature_level();
+    let chunk_files = manifest
+        .chunk_list
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            let file = FChunk::new(chunk, chunk_data[chunk.guid()].clone())
+                .serialize()
+                .unwrap();
+            (chunk.cloud_path(feature_level), file)
+        })
+        .collect();
+
+    (manifest, chunk_files)
+}
+
+fn manifest_bytes() -> Vec<u8> {
+    manifest_and_chunk_files().0.serialize().unwrap()
+}
+
+/// Cloud path of the chunk used by the `part`th chunk part of `filename`
+fn part_cloud_path(manifest: &FManifest, filename: &str, part: usize) -> String {
+    let guid = manifest.find_file(filename).unwrap().chunk_parts()[part].guid();
+
+    manifest
+        .chunk_list
+        .find_by_guid(guid)
+        .unwrap()
+        .cloud_path(manifest.meta.feature_level())
+}
+
+/// Keeps the header of a compressed chunk file but replaces its data with bytes that aren't zlib
+fn corrupted(chunk_file: &[u8]) -> Vec<u8> {
+    let header_size = FChunk::parse(chunk_file).unwrap().header().header_size() as usize;
+    let mut corrupted = chunk_file[..header_size].to_vec();
+    corrupted.extend_from_slice(b"not zlib data");
+    corrupted
+}
+
+/// A `fetch` callback serving `chunk_files` by cloud path, counting how often it is called
+fn mock_fetch(chunk_files: HashMap<String, Vec<u8>>, calls: Rc<Cell<u32>>) -> Function {
+    let fetch = Closure::<dyn FnMut(String) -> Promise>::new(move |cloud_path: String| {
+        calls.set(calls.get() + 1);
+        match chunk_files.get(&cloud_path) {
+            Some(file) => Promise::resolve(&JsValue::from(Uint8Array::from(&file[..]))),
+            None => Promise::reject(&JsValue::from_str("404")),
+        }
+    });
+
+    fetch.into_js_value().unchecked_into()
+}
+
+/// The chunk data object `extract_file` takes, mapping the GUIDs formatted by `key` to the chunk files
+fn chunk_data_object(
+    manifest: &FManifest,
+    chunk_files: &HashMap<String, Vec<u8>>,
+    key: fn(&FGuid) -> String,
+) -> JsValue {
+    let object = js_sys::Object::new();
+    for chunk in manifest.chunk_list.chunks() {
+        let file = &chunk_files[&chunk.cloud_path(manifest.meta.feature_level())];
+        Reflect::set(
+            &object,
+            &JsValue::from_str(&key(chunk.guid())),
+            &Uint8Array::from(&file[..]),
+        )
+        .unwrap();
+    }
+
+    object.into()
+}
+
+#[wasm_bindgen_test]
+fn handle_answers_queries_without_reparsing() {
+    let handle = ManifestHandle::new(&manifest_bytes()).unwrap();
+
+    assert_eq!(handle.app_name(), "TestApp");
+    assert_eq!(handle.file_count(), 2);
+    assert_eq!(handle.file_names(), vec!["a.bin", "b.bin"]);
+
+    let plan = handle.chunk_plan("a.bin").unwrap();
+    let file_size = Reflect::get(&plan, &JsValue::from_str("fileSize")).unwrap();
+    assert_eq!(file_size.as_f64(), Some(12.0));
+    let parts = Reflect::get(&plan, &JsValue::from_str("parts")).unwrap();
+    assert_eq!(js_sys::Array::from(&parts).length(), 3);
+}
+
+#[wasm_bindgen_test]
+fn handle_chunk_plan_of_a_missing_file_throws() {
+    let handle = ManifestHandle::new(&manifest_bytes()).unwrap();
+
+    assert!(handle.chunk_plan("missing.bin").is_err());
+}
+
+#[wasm_bindgen_test]
+fn handle_of_an_invalid_manifest_throws() {
+    assert!(ManifestHandle::new(b"not a manifest").is_err());
+}
+
+#[wasm_bindgen_test]
+fn version_info_is_read_from_the_header_and_meta() {
+    let info = manifest_version_info(&manifest_bytes()).unwrap();
+
+    let field = |name| Reflect::get(&info, &JsValue::from_str(name)).unwrap();
+    assert_eq!(
+        field("featureLevelName").as_string().as_deref(),
+        Some("UsesBuildTimeGeneratedBuildId")
+    );
+    assert_eq!(field("isChunked").as_bool(), Some(true));
+    assert_eq!(field("supportsVariableChunks").as_bool(), Some(true));
+}
+
+#[wasm_bindgen_test]
+async fn download_file_fetches_each_chunk_once() {
+    let (manifest, chunk_files) = manifest_and_chunk_files();
+    let calls = Rc::new(Cell::new(0));
+    let fetch = mock_fetch(chunk_files, calls.clone());
+
+    let file = download_file(manifest.serialize().unwrap(), "a.bin".to_string(), fetch)
+        .await
+        .unwrap();
+
+    assert_eq!(file, b"abcdabcdefgh");
+    assert_eq!(calls.get(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn download_file_rejects_when_a_chunk_is_missing() {
+    let (manifest, mut chunk_files) = manifest_and_chunk_files();
+    chunk_files.remove(&part_cloud_path(&manifest, "a.bin", 2));
+    let fetch = mock_fetch(chunk_files, Rc::new(Cell::new(0)));
+
+    let result = download_file(manifest.serialize().unwrap(), "a.bin".to_string(), fetch).await;
+
+    assert_eq!(result.unwrap_err().as_string().as_deref(), Some("404"));
+}
+
+#[wasm_bindgen_test]
+async fn download_file_rejects_the_wrong_chunk() {
+    let (manifest, mut chunk_files) = manifest_and_chunk_files();
+    // Serve the chunk of b.bin at every path, like a stale CDN cache
+    let wrong = chunk_files[&part_cloud_path(&manifest, "b.bin", 0)].clone();
+    for file in chunk_files.values_mut() {
+        *file = wrong.clone();
+    }
+    let fetch = mock_fetch(chunk_files, Rc::new(Cell::new(0)));
+
+    let result = download_file(manifest.serialize().unwrap(), "a.bin".to_string(), fetch).await;
+
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+async fn download_file_rejects_a_corrupt_chunk() {
+    let (manifest, mut chunk_files) = manifest_and_chunk_files();
+    let path = part_cloud_path(&manifest, "a.bin", 0);
+    let corrupt = corrupted(&chunk_files[&path]);
+    chunk_files.insert(path, corrupt);
+    let fetch = mock_fetch(chunk_files, Rc::new(Cell::new(0)));
+
+    let result = download_file(manifest.serialize().unwrap(), "a.bin".to_string(), fetch).await;
+
+    assert!(result
+        .unwrap_err()
+        .as_string()
+        .unwrap()
+        .contains("DecompressionError"));
+}
+
+#[wasm_bindgen_test]
+fn group_histogram_has_a_count_per_group() {
+    let histogram = manifest_group_histogram(&manifest_bytes()).unwrap();
+
+    assert_eq!(histogram.len(), 256);
+    assert_eq!(histogram.iter().sum::<u32>(), 3);
+}
+
+#[wasm_bindgen_test]
+fn extract_file_assembles_from_the_given_chunks() {
+    let (manifest, chunk_files) = manifest_and_chunk_files();
+    let chunk_data = chunk_data_object(&manifest, &chunk_files, FGuid::to_hex_lower);
+
+    let file = extract_file(&manifest.serialize().unwrap(), "a.bin", chunk_data).unwrap();
+
+    assert_eq!(file, b"abcdabcdefgh");
+}
+
+#[wasm_bindgen_test]
+fn extract_file_accepts_uppercase_guids() {
+    let (manifest, chunk_files) = manifest_and_chunk_files();
+    let chunk_data = chunk_data_object(&manifest, &chunk_files, FGuid::to_hex_upper);
+
+    let file = extract_file(&manifest.serialize().unwrap(), "b.bin", chunk_data).unwrap();
+
+    assert_eq!(file, b"ijkl");
+}
+
+#[wasm_bindgen_test]
+fn extract_file_throws_when_a_chunk_is_missing() {
+    let (manifest, chunk_files) = manifest_and_chunk_files();
+    let chunk_data = chunk_data_object(&manifest, &chunk_files, FGuid::to_hex_lower);
+    let missing = manifest.find_file("a.bin").unwrap().chunk_parts()[2].guid();
+    Reflect::delete_property(
+        chunk_data.unchecked_ref::<js_sys::Object>(),
+        &JsValue::from_str(&missing.to_hex_lower()),
+    )
+    .unwrap();
+
+    let error = extract_file(&manifest.serialize().unwrap(), "a.bin", chunk_data).unwrap_err();
+
+    assert!(error.as_string().unwrap().contains(&missing.to_hex_lower()));
+}
+
+#[wasm_bindgen_test]
+fn create_manifest_of_invalid_json_throws() {
+    let error = create_manifest("{ not json").unwrap_err();
+
+    assert!(error
+        .as_string()
+        .unwrap()
+        .starts_with("Failed to create manifest: JSON error"));
+}
+
+#[wasm_bindgen_test]
+fn extract_file_throws_on_a_corrupt_chunk() {
+    let (manifest, mut chunk_files) = manifest_and_chunk_files();
+    let path = part_cloud_path(&manifest, "b.bin", 0);
+    let corrupt = corrupted(&chunk_files[&path]);
+    chunk_files.insert(path, corrupt);
+    let chunk_data = chunk_data_object(&manifest, &chunk_files, FGuid::to_hex_lower);
+
+    let error = extract_file(&manifest.serialize().unwrap(), "b.bin", chunk_data).unwrap_err();
+
+    assert!(error.as_string().unwrap().contains("DecompressionError"));
+}